@@ -1,10 +1,30 @@
-use crate::dns::{test_download_speed_with_dns, test_single_dns_server, DNS_SERVERS};
+use crate::dns::{
+    connect_latency_result, failed_connect_latency_result, failed_download_result, ConnectLatencyStats,
+    probe_connect_latency, rank_mirror_results, resolve_download_host, resolve_host,
+    select_addresses, summarize_soak, test_download_speed_with_dns, test_librespeed_with_dns,
+    test_single_dns_server, test_upload_speed_with_dns, DownloadProgress, DownloadSpeedResult,
+    DownloadTestOptions, LibreSpeedOptions, ProgressCallback, UploadProgress, UploadProgressCallback,
+    SoakSample, UploadTestOptions, DNS_SERVERS, SYSTEM_DNS,
+};
 use crate::docker::{
     docker_config_path, download_docker_config_file, read_docker_registries_file,
-    test_docker_registry_download_speed, validate_docker_image_name, DOCKER_CONFIG_URL,
+    clear_credential_helper_cache, test_docker_registry_download_speed, ImageReference, TargetPlatform,
+    DOCKER_CONFIG_URL,
+};
+use crate::downloader::{download_file, FileDownloadError, FileDownloadRequest};
+use crate::presets::{
+    domain_presets_config_path, find_domain_preset, load_domain_presets,
+    write_domain_presets_file, DomainPreset,
+};
+use crate::usage::{
+    current_data_usage, reset_session_usage, take_budget_exceeded_report,
+    DataUsage,
 };
+use futures::StreamExt;
 use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use tauri::{AppHandle, Emitter};
 use tokio::task::JoinHandle;
 
@@ -33,12 +53,26 @@ where
     }
 }
 
+const MAX_DOWNLOAD_CONCURRENCY: usize = 8;
+// Lookups are light, so they run with more parallelism than the downloads
+const MAX_CONCURRENT_RESOLUTIONS: usize = 16;
+const DEFAULT_CONNECT_ATTEMPTS: u32 = 10;
+const MAX_CONNECT_ATTEMPTS: u32 = 100;
+const DEFAULT_STABILITY_INTERVAL_SECONDS: u64 = 10;
+const MAX_STABILITY_DURATION_SECONDS: u64 = 24 * 60 * 60;
+
 lazy_static::lazy_static! {
     static ref ACTIVE_TASKS: Arc<Mutex<HashMap<String, Vec<JoinHandle<()>>>>> = Arc::new(Mutex::new(HashMap::new()));
+    // File downloads are tracked separately so starting a test doesn't pause them
+    static ref ACTIVE_DOWNLOADS: Arc<Mutex<HashMap<String, JoinHandle<()>>>> = Arc::new(Mutex::new(HashMap::new()));
 }
 
 #[tauri::command]
-pub async fn test_dns_servers(domain: String, app_handle: AppHandle) -> Result<(), String> {
+pub async fn test_dns_servers(
+    domain: String,
+    preset: Option<String>,
+    app_handle: AppHandle,
+) -> Result<(), String> {
     println!("Testing DNS servers for domain: {}", domain);
 
     {
@@ -48,44 +82,190 @@ pub async fn test_dns_servers(domain: String, app_handle: AppHandle) -> Result<(
         }
     }
 
-    let domain = domain.trim().to_string();
+    // A preset name takes precedence over the single domain input
+    let domains: Vec<String> = match preset.as_deref().map(str::trim) {
+        Some(name) if !name.is_empty() => {
+            let preset = find_domain_preset(name)
+                .await
+                .map_err(|e| format!("Failed to load domain preset: {}", e))?;
+            preset
+                .domains
+                .iter()
+                .map(|d| d.trim().to_string())
+                .filter(|d| !d.is_empty())
+                .collect()
+        }
+        _ => {
+            let domain = domain.trim().to_string();
+            if domain.is_empty() {
+                return Err("Please enter a valid domain name".to_string());
+            }
+            vec![domain]
+        }
+    };
+
+    if domains.is_empty() {
+        return Err("Selected preset has no domains".to_string());
+    }
+
+    let total_tests = domains.len() * DNS_SERVERS.len();
+    let results_count = Arc::new(Mutex::new(0));
+
+    for domain in &domains {
+        for &dns_server in DNS_SERVERS {
+            let domain_clone = domain.clone();
+            let dns_server_string = dns_server.to_string();
+            let app_handle_clone = app_handle.clone();
+            let results_count_clone = Arc::clone(&results_count);
+            let task_key = domain.clone() + "-" + dns_server;
+
+            spawn_with_cleanup(task_key.clone(), move || async move {
+                let result = test_single_dns_server(domain_clone, dns_server_string, 0).await;
+
+                if let Err(e) = app_handle_clone.emit("dns-test-result", &result) {
+                    eprintln!("Failed to emit DNS test result: {}", e);
+                }
+                let mut result_count = results_count_clone.lock().unwrap();
+                *result_count += 1;
 
+                if *result_count == total_tests {
+                    if let Err(e) = app_handle_clone.emit("dns-test-complete", ()) {
+                        eprintln!("Failed to emit completion event: {}", e);
+                    }
+                }
+            }).await;
+        }
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn test_dns_stability(
+    domain: String,
+    dns_servers: Vec<String>,
+    interval_seconds: Option<u64>,
+    duration_seconds: u64,
+    app_handle: AppHandle,
+) -> Result<(), String> {
+    {
+        let result = abort_all_tasks().await;
+        if let Err(e) = result {
+            eprintln!("Failed to abort all tasks: {}", e);
+        }
+    }
+
+    let domain = domain.trim().to_string();
     if domain.is_empty() {
         return Err("Please enter a valid domain name".to_string());
     }
 
-    let results_count = Arc::new(Mutex::new(1));
+    let dns_servers: Vec<String> = dns_servers
+        .iter()
+        .map(|dns_server| dns_server.trim().to_string())
+        .filter(|dns_server| !dns_server.is_empty())
+        .collect();
+    if dns_servers.is_empty() {
+        return Err("Please select at least one DNS server".to_string());
+    }
+
+    let interval_seconds = interval_seconds.unwrap_or(DEFAULT_STABILITY_INTERVAL_SECONDS).max(1);
+    if duration_seconds < interval_seconds {
+        return Err("Test duration must be at least one probe interval".to_string());
+    }
+    if duration_seconds > MAX_STABILITY_DURATION_SECONDS {
+        return Err("Test duration can be at most 24 hours".to_string());
+    }
 
-    for &dns_server in DNS_SERVERS {
-        let domain_clone = domain.clone();
-        let dns_server_string = dns_server.to_string();
-        let app_handle_clone = app_handle.clone();
-        let results_count_clone = Arc::clone(&results_count);
-        let task_key = domain.clone() + "-" + dns_server;
+    spawn_with_cleanup(format!("stability-{}", domain), move || async move {
+        println!(
+            "Starting stability test for {} via {:?} every {}s for {}s",
+            domain, dns_servers, interval_seconds, duration_seconds
+        );
 
-        spawn_with_cleanup(task_key.clone(), move || async move {
-            let result = test_single_dns_server(domain_clone, dns_server_string, 0).await;
+        let test_start = std::time::Instant::now();
+        let duration = Duration::from_secs(duration_seconds);
+        let mut samples: Vec<Vec<SoakSample>> = vec![Vec::new(); dns_servers.len()];
+        let mut ticker = tokio::time::interval(Duration::from_secs(interval_seconds));
+        // A slow round shouldn't be followed by a burst of catch-up probes
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
 
-            if let Err(e) = app_handle_clone.emit("dns-test-result", &result) {
-                eprintln!("Failed to emit DNS test result: {}", e);
+        let mut round = 0;
+        loop {
+            ticker.tick().await;
+            if test_start.elapsed() >= duration {
+                break;
             }
-            let mut result_count = results_count_clone.lock().unwrap();
-            *result_count += 1;
 
-            if *result_count == DNS_SERVERS.len() {
-                if let Err(e) = app_handle_clone.emit("dns-test-complete", ()) {
-                    eprintln!("Failed to emit completion event: {}", e);
+            let elapsed_seconds = test_start.elapsed().as_secs_f64();
+            let results = futures::future::join_all(
+                dns_servers
+                    .iter()
+                    .map(|dns_server| test_single_dns_server(domain.clone(), dns_server.clone(), 0)),
+            )
+            .await;
+
+            for (server_samples, result) in samples.iter_mut().zip(results) {
+                let sample = SoakSample {
+                    dns_server: result.dns_server,
+                    domain: domain.clone(),
+                    round,
+                    elapsed_seconds,
+                    success: result.status,
+                    response_time_ms: result.response_time,
+                    error_message: result.error_message,
+                };
+                if let Err(e) = app_handle.emit("stability-sample", &sample) {
+                    eprintln!("Failed to emit stability sample: {}", e);
                 }
+                server_samples.push(sample);
             }
-        }).await;
-    }
+            round += 1;
+        }
+
+        let ended_at_seconds = test_start.elapsed().as_secs_f64();
+        let summaries: Vec<_> = dns_servers
+            .iter()
+            .zip(&samples)
+            .map(|(dns_server, server_samples)| summarize_soak(dns_server, &domain, server_samples, ended_at_seconds))
+            .collect();
+
+        println!("Stability test completed: {:?}", summaries);
+        if let Err(e) = app_handle.emit("stability-test-complete", &summaries) {
+            eprintln!("Failed to emit stability completion event: {}", e);
+        }
+    }).await;
+
     Ok(())
 }
 
+// Servers every DNS test runs against, so the frontend can size its progress
+#[tauri::command]
+pub fn get_dns_servers() -> Vec<String> {
+    DNS_SERVERS.iter().map(|dns_server| dns_server.to_string()).collect()
+}
+
+#[tauri::command]
+pub async fn get_domain_presets() -> Result<Vec<DomainPreset>, String> {
+    load_domain_presets()
+        .await
+        .map_err(|e| format!("Failed to load domain presets: {}", e))
+}
+
+#[tauri::command]
+pub async fn save_domain_presets(presets: Vec<DomainPreset>) -> Result<(), String> {
+    write_domain_presets_file(&domain_presets_config_path(), &presets)
+        .await
+        .map_err(|e| format!("Failed to save domain presets: {}", e))
+}
+
 #[tauri::command]
 pub async fn test_download_speed_all_dns(
     url: String,
     timeout_seconds: u64,
+    max_bytes: Option<u64>,
+    concurrency: Option<usize>,
+    dedupe_by_ip: Option<bool>,
+    options: Option<DownloadTestOptions>,
     app_handle: AppHandle,
 ) -> Result<(), String> {
     {
@@ -105,45 +285,550 @@ pub async fn test_download_speed_all_dns(
         return Err("URL must start with http:// or https://".to_string());
     }
 
+    // Downloads share one link, so keep parallelism small to avoid skewing results
+    let concurrency = concurrency.unwrap_or(1).clamp(1, MAX_DOWNLOAD_CONCURRENCY);
+    // Most resolvers return the same CDN address, so measure each address only once by default
+    let dedupe_by_ip = dedupe_by_ip.unwrap_or(true);
+    let mut options = options.unwrap_or_default();
+    // The top-level argument is shorthand for options.max_bytes and wins when both are given
+    options.max_bytes = max_bytes.or(options.max_bytes).filter(|&max_bytes| max_bytes > 0);
+
+    validate_headers(&options.headers)?;
+
+
     let url_for_storage = url.clone();
 
     spawn_with_cleanup(url_for_storage.clone(), move || async move {
-        println!("Starting download tests for URL: {}", url);
+        println!(
+            "Starting download tests for URL: {} (concurrency={}, dedupe_by_ip={})",
+            url, concurrency, dedupe_by_ip
+        );
 
-        for (index, &dns_server) in DNS_SERVERS.iter().enumerate() {
-            println!(
-                "Testing DNS server {} ({}/{}): {}",
+        if dedupe_by_ip {
+            run_deduplicated_download_tests(&url, timeout_seconds, &options, concurrency, &app_handle)
+                .await;
+        } else {
+            let mut servers: Vec<String> = DNS_SERVERS.iter().map(|dns_server| dns_server.to_string()).collect();
+            if options.randomize_order {
+                fastrand::shuffle(&mut servers);
+            }
+            let servers: Vec<(usize, String)> = servers.into_iter().enumerate().collect();
+
+            futures::stream::iter(servers)
+                .map(|(index, dns_server)| {
+                    let url_clone = url.clone();
+                    let app_handle_clone = app_handle.clone();
+                    let options = &options;
+                    async move {
+                        println!(
+                            "Testing DNS server {} ({}/{})",
+                            dns_server,
+                            index + 1,
+                            DNS_SERVERS.len()
+                        );
+
+                        let mut result = test_download_speed_with_dns(
+                            url_clone,
+                            dns_server,
+                            timeout_seconds,
+                            options,
+                            0,
+                            Some(download_progress_emitter(&app_handle_clone)),
+                            None,
+                        )
+                        .await;
+                        result.test_position = Some(index + 1);
+
+                        emit_download_result(&app_handle_clone, &result);
+                    }
+                })
+                .buffer_unordered(concurrency)
+                .collect::<Vec<()>>()
+                .await;
+        }
+
+        println!("All download tests completed");
+        if let Err(e) = app_handle.emit("download-test-complete", ()) {
+            eprintln!("Failed to emit completion event: {}", e);
+        } else {
+            println!("Successfully emitted completion event");
+        }
+    }).await;
+
+    Ok(())
+}
+
+// Reject malformed custom headers up front instead of failing every test the same way
+fn validate_headers(headers: &HashMap<String, String>) -> Result<(), String> {
+    for (name, value) in headers {
+        if reqwest::header::HeaderName::from_bytes(name.as_bytes()).is_err() {
+            return Err(format!("Invalid header name: {}", name));
+        }
+        if reqwest::header::HeaderValue::from_str(value).is_err() {
+            return Err(format!("Invalid value for header {}", name));
+        }
+    }
+    Ok(())
+}
+
+// Resolve through every server first, then download once per distinct address
+async fn run_deduplicated_download_tests(
+    url: &str,
+    timeout_seconds: u64,
+    options: &DownloadTestOptions,
+    concurrency: usize,
+    app_handle: &AppHandle,
+) {
+    let lookups: Vec<_> = DNS_SERVERS
+        .iter()
+        .enumerate()
+        .map(|(index, &dns_server)| async move {
+            (
+                index,
                 dns_server,
-                index + 1,
-                DNS_SERVERS.len(),
-                dns_server
+                resolve_download_host(url, dns_server, timeout_seconds, options.test_all_addresses).await,
+            )
+        })
+        .collect();
+    let mut resolutions: Vec<_> = futures::stream::iter(lookups)
+        .buffer_unordered(MAX_CONCURRENT_RESOLUTIONS)
+        .collect()
+        .await;
+    resolutions.sort_by_key(|(index, _, _)| *index);
+
+    // Group servers by the addresses that will be measured, keeping the original server order within each group.
+    // With every address tested, that's the whole answer, sorted so the order resolvers return it in doesn't matter
+    let mut groups: Vec<(Vec<IpAddr>, Vec<_>)> = Vec::new();
+    for (_, dns_server, resolution) in resolutions {
+        match resolution {
+            Ok(resolved) => {
+                let key = if options.test_all_addresses {
+                    let mut addresses = resolved.addresses.clone();
+                    addresses.sort();
+                    addresses.dedup();
+                    addresses
+                } else {
+                    vec![resolved.ip]
+                };
+                match groups.iter_mut().find(|(addresses, _)| *addresses == key) {
+                    Some((_, servers)) => servers.push((dns_server.to_string(), resolved)),
+                    None => groups.push((key, vec![(dns_server.to_string(), resolved)])),
+                }
+            }
+            Err(e) => {
+                let result = failed_download_result(
+                    dns_server.to_string(),
+                    url.to_string(),
+                    e.to_string(),
+                    0,
+                );
+                emit_download_result(app_handle, &result);
+            }
+        }
+    }
+
+    println!(
+        "Resolved {} to {} distinct address sets, downloading once per set",
+        url,
+        groups.len()
+    );
+
+    if options.randomize_order {
+        fastrand::shuffle(&mut groups);
+    }
+
+    futures::stream::iter(groups.into_iter().enumerate())
+        .map(|(index, (addresses, servers))| async move {
+            let (measured_server, measured_resolution) = servers[0].clone();
+            println!(
+                "Testing {:?} via DNS {} (shared by {} servers)",
+                addresses,
+                measured_server,
+                servers.len()
             );
+            // With several addresses the measurement reports whichever was fastest
+            let shared_ip = (addresses.len() == 1).then(|| addresses[0].to_string());
 
-            let url_clone = url.clone();
-            let dns_server_string = dns_server.to_string();
-            let app_handle_clone = app_handle.clone();
+            let mut measurement = test_download_speed_with_dns(
+                url.to_string(),
+                measured_server,
+                timeout_seconds,
+                options,
+                0,
+                Some(download_progress_emitter(app_handle)),
+                Some(measured_resolution),
+            )
+            .await;
+            if shared_ip.is_some() {
+                measurement.resolved_ip = shared_ip.clone();
+            }
+            measurement.test_position = Some(index + 1);
+
+            emit_download_result(app_handle, &measurement);
+
+            // Every other server in the group reports the shared measurement with its own resolution time
+            for (dns_server, resolved) in servers.into_iter().skip(1) {
+                let mut result = measurement.clone();
+                result.dns_server = dns_server;
+                result.resolution_time_ms = Some(resolved.resolution_time_ms);
+                result.measured_via_dns_server = Some(measurement.dns_server.clone());
+                emit_download_result(app_handle, &result);
+            }
+        })
+        .buffer_unordered(concurrency)
+        .collect::<Vec<()>>()
+        .await;
+}
+
+fn download_progress_emitter(app_handle: &AppHandle) -> ProgressCallback {
+    let progress_handle = app_handle.clone();
+    Arc::new(move |progress: DownloadProgress| {
+        if let Err(e) = progress_handle.emit("download-test-progress", &progress) {
+            eprintln!("Failed to emit download test progress: {}", e);
+        }
+        emit_data_usage(&progress_handle);
+    })
+}
+
+// Report running data usage and, once per session, that the budget has been used up
+fn emit_data_usage(app_handle: &AppHandle) {
+    let usage = current_data_usage();
+    if let Err(e) = app_handle.emit("data-usage-update", &usage) {
+        eprintln!("Failed to emit data usage: {}", e);
+    }
 
-            let result =
-                test_download_speed_with_dns(url_clone, dns_server_string, timeout_seconds, 0)
-                    .await;
+    if take_budget_exceeded_report() {
+        println!(
+            "Data budget exceeded: {} bytes used of {:?}, skipping remaining tests",
+            usage.session_bytes, usage.budget_bytes
+        );
+        if let Err(e) = app_handle.emit("data-budget-exceeded", &usage) {
+            eprintln!("Failed to emit data budget exceeded event: {}", e);
+        }
+    }
+}
+
+fn emit_download_result(app_handle: &AppHandle, result: &DownloadSpeedResult) {
+    println!(
+        "Download test result for {}: success={}, speed={:.3} Mbps",
+        result.dns_server, result.success, result.download_speed_mbps
+    );
+
+    if let Err(e) = app_handle.emit("download-test-result", result) {
+        eprintln!("Failed to emit download test result: {}", e);
+    } else {
+        println!("Successfully emitted result for {}", result.dns_server);
+    }
+    emit_data_usage(app_handle);
+}
+
+#[tauri::command]
+pub async fn test_download_mirrors(
+    urls: Vec<String>,
+    timeout_seconds: u64,
+    dns_server: Option<String>,
+    options: Option<DownloadTestOptions>,
+    app_handle: AppHandle,
+) -> Result<(), String> {
+    {
+        let result = abort_all_tasks().await;
+        if let Err(e) = result {
+            eprintln!("Failed to abort all tasks: {}", e);
+        }
+    }
+
+    let urls: Vec<String> = urls
+        .iter()
+        .map(|url| url.trim().to_string())
+        .filter(|url| !url.is_empty())
+        .collect();
+
+    if urls.len() < 2 {
+        return Err("Please enter at least two mirror URLs".to_string());
+    }
+
+    if let Some(url) = urls
+        .iter()
+        .find(|url| !url.starts_with("http://") && !url.starts_with("https://"))
+    {
+        return Err(format!("URL must start with http:// or https://: {}", url));
+    }
+
+    // Without a chosen server, mirrors are compared through the system resolver
+    let dns_server = dns_server
+        .map(|dns_server| dns_server.trim().to_string())
+        .filter(|dns_server| !dns_server.is_empty())
+        .unwrap_or_else(|| SYSTEM_DNS.to_string());
+    let options = options.unwrap_or_default();
+    validate_headers(&options.headers)?;
+
+
+    spawn_with_cleanup(format!("mirrors-{}", urls.join(",")), move || async move {
+        println!("Racing {} mirrors through DNS {}", urls.len(), dns_server);
+
+        // Mirrors run one at a time so they don't compete for the same link
+        let mut results = Vec::new();
+        for (index, url) in urls.iter().enumerate() {
+            println!("Testing mirror {}/{}: {}", index + 1, urls.len(), url);
+
+            let result = test_download_speed_with_dns(
+                url.clone(),
+                dns_server.clone(),
+                timeout_seconds,
+                &options,
+                0,
+                Some(download_progress_emitter(&app_handle)),
+                None,
+            )
+            .await;
+
+            if let Err(e) = app_handle.emit("mirror-test-result", &result) {
+                eprintln!("Failed to emit mirror test result: {}", e);
+            }
+            emit_data_usage(&app_handle);
+            results.push(result);
+        }
+
+        let ranking = rank_mirror_results(&results);
+        println!("Mirror ranking: {:?}", ranking);
+        if let Err(e) = app_handle.emit("mirror-test-complete", &ranking) {
+            eprintln!("Failed to emit mirror completion event: {}", e);
+        }
+    }).await;
+
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn test_upload_speed_all_dns(
+    url: String,
+    timeout_seconds: u64,
+    options: Option<UploadTestOptions>,
+    app_handle: AppHandle,
+) -> Result<(), String> {
+    {
+        let result = abort_all_tasks().await;
+        if let Err(e) = result {
+            eprintln!("Failed to abort all tasks: {}", e);
+        }
+    }
+
+    let url = url.trim().to_string();
+
+    if url.is_empty() {
+        return Err("Please enter a valid upload URL".to_string());
+    }
+
+    if !url.starts_with("http://") && !url.starts_with("https://") {
+        return Err("URL must start with http:// or https://".to_string());
+    }
+
+    let options = options.unwrap_or_default();
+    validate_headers(&options.headers)?;
+
+    spawn_with_cleanup(format!("upload-{}", url), move || async move {
+        println!("Starting upload tests for URL: {}", url);
+
+        let progress_handle = app_handle.clone();
+        let on_progress: UploadProgressCallback = Arc::new(move |progress: UploadProgress| {
+            if let Err(e) = progress_handle.emit("upload-test-progress", &progress) {
+                eprintln!("Failed to emit upload test progress: {}", e);
+            }
+        });
+
+        // Uploads saturate the uplink, so servers are always tested one at a time
+        for (index, &dns_server) in DNS_SERVERS.iter().enumerate() {
+            println!("Testing upload via DNS server {} ({}/{})", dns_server, index + 1, DNS_SERVERS.len());
+
+            let result = test_upload_speed_with_dns(
+                url.clone(),
+                dns_server.to_string(),
+                timeout_seconds,
+                &options,
+                0,
+                Some(on_progress.clone()),
+            )
+            .await;
 
             println!(
-                "Download test result for {}: success={}, speed={:.3} Mbps",
-                result.dns_server, result.success, result.download_speed_mbps
+                "Upload test result for {}: success={}, speed={:.3} Mbps",
+                result.dns_server, result.success, result.upload_speed_mbps
             );
+            if let Err(e) = app_handle.emit("upload-test-result", &result) {
+                eprintln!("Failed to emit upload test result: {}", e);
+            }
+            emit_data_usage(&app_handle);
+        }
 
-            if let Err(e) = app_handle_clone.emit("download-test-result", &result) {
-                eprintln!("Failed to emit download test result: {}", e);
-            } else {
-                println!("Successfully emitted result for {}", result.dns_server);
+        println!("All upload tests completed");
+        if let Err(e) = app_handle.emit("upload-test-complete", ()) {
+            eprintln!("Failed to emit upload completion event: {}", e);
+        }
+    }).await;
+
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn test_connect_latency_all_dns(
+    target: String,
+    port: Option<u16>,
+    attempts: Option<u32>,
+    timeout_seconds: u64,
+    all_addresses: Option<bool>,
+    max_addresses: Option<usize>,
+    app_handle: AppHandle,
+) -> Result<(), String> {
+    {
+        let result = abort_all_tasks().await;
+        if let Err(e) = result {
+            eprintln!("Failed to abort all tasks: {}", e);
+        }
+    }
+
+    let (host, port) = parse_probe_target(&target, port)?;
+    let attempts = attempts.unwrap_or(DEFAULT_CONNECT_ATTEMPTS).clamp(1, MAX_CONNECT_ATTEMPTS);
+    // Probing every address includes the AAAA records, so IPv4 and IPv6 paths can be compared
+    let all_addresses = all_addresses.unwrap_or(false);
+
+    spawn_with_cleanup(format!("latency-{}:{}", host, port), move || async move {
+        println!("Starting connect latency probes for {}:{} ({} attempts)", host, port, attempts);
+
+        let resolutions = futures::future::join_all(DNS_SERVERS.iter().map(|&dns_server| {
+            let host = &host;
+            async move { (dns_server, resolve_host(host, dns_server, timeout_seconds, all_addresses).await) }
+        }))
+        .await;
+
+        // Probe each distinct address once; servers that agree share the measurement.
+        // Sequential so concurrent handshakes don't inflate each other's RTT
+        let mut probed: HashMap<IpAddr, ConnectLatencyStats> = HashMap::new();
+        for (dns_server, resolution) in resolutions {
+            let resolved = match resolution {
+                Ok(resolved) => resolved,
+                Err(e) => {
+                    let result = failed_connect_latency_result(dns_server.to_string(), host.clone(), port, e.to_string());
+                    if let Err(e) = app_handle.emit("latency-test-result", &result) {
+                        eprintln!("Failed to emit latency test result: {}", e);
+                    }
+                    continue;
+                }
+            };
+
+            // One result per server and address
+            for ip in select_addresses(&resolved, all_addresses, max_addresses, port).await {
+                let stats = match probed.get(&ip) {
+                    Some(stats) => *stats,
+                    None => {
+                        let stats = probe_connect_latency(
+                            SocketAddr::new(ip, port),
+                            attempts,
+                            Duration::from_secs(timeout_seconds),
+                        )
+                        .await;
+                        println!("Connect latency for {}: {:?}", ip, stats);
+                        probed.insert(ip, stats);
+                        stats
+                    }
+                };
+
+                let result = connect_latency_result(dns_server.to_string(), host.clone(), port, &resolved, ip, &stats);
+                if let Err(e) = app_handle.emit("latency-test-result", &result) {
+                    eprintln!("Failed to emit latency test result: {}", e);
+                }
             }
         }
 
-        println!("All download tests completed");
-        if let Err(e) = app_handle.emit("download-test-complete", ()) {
-            eprintln!("Failed to emit completion event: {}", e);
-        } else {
-            println!("Successfully emitted completion event");
+        println!("All connect latency probes completed");
+        if let Err(e) = app_handle.emit("latency-test-complete", ()) {
+            eprintln!("Failed to emit latency completion event: {}", e);
+        }
+    }).await;
+
+    Ok(())
+}
+
+// Accept a URL, `host:port` or a bare host; the port defaults to the URL scheme's or 443
+fn parse_probe_target(target: &str, port: Option<u16>) -> Result<(String, u16), String> {
+    let target = target.trim();
+    if target.is_empty() {
+        return Err("Please enter a host or URL".to_string());
+    }
+
+    let url = if target.contains("://") {
+        target.to_string()
+    } else {
+        format!("https://{}", target)
+    };
+    let parsed_url = url::Url::parse(&url).map_err(|e| format!("Invalid target {}: {}", target, e))?;
+    let host = parsed_url
+        .host_str()
+        .ok_or_else(|| format!("Invalid target {}: missing host", target))?
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .to_string();
+    let port = port
+        .or_else(|| parsed_url.port_or_known_default())
+        .ok_or_else(|| format!("Please enter a port for {}", target))?;
+
+    Ok((host, port))
+}
+
+#[tauri::command]
+pub async fn test_librespeed_server(
+    server_url: String,
+    dns_servers: Option<Vec<String>>,
+    options: Option<LibreSpeedOptions>,
+    app_handle: AppHandle,
+) -> Result<(), String> {
+    {
+        let result = abort_all_tasks().await;
+        if let Err(e) = result {
+            eprintln!("Failed to abort all tasks: {}", e);
+        }
+    }
+
+    let server_url = server_url.trim().to_string();
+
+    if server_url.is_empty() {
+        return Err("Please enter the speed test server URL".to_string());
+    }
+
+    if !server_url.starts_with("http://") && !server_url.starts_with("https://") {
+        return Err("URL must start with http:// or https://".to_string());
+    }
+
+    // Without a selection the server is tested through the system resolver only
+    let mut dns_servers: Vec<String> = dns_servers
+        .unwrap_or_default()
+        .iter()
+        .map(|dns_server| dns_server.trim().to_string())
+        .filter(|dns_server| !dns_server.is_empty())
+        .collect();
+    if dns_servers.is_empty() {
+        dns_servers.push(SYSTEM_DNS.to_string());
+    }
+    let options = options.unwrap_or_default();
+
+
+    spawn_with_cleanup(format!("librespeed-{}", server_url), move || async move {
+        println!("Starting LibreSpeed tests against {} via {:?}", server_url, dns_servers);
+
+        // One server at a time, since each run already saturates the link with parallel streams
+        for dns_server in dns_servers {
+            let result = test_librespeed_with_dns(server_url.clone(), dns_server, &options, 0).await;
+            println!(
+                "LibreSpeed result for {}: success={}, download={:?} Mbps, upload={:?} Mbps",
+                result.dns_server, result.success, result.download_mbps, result.upload_mbps
+            );
+            if let Err(e) = app_handle.emit("librespeed-test-result", &result) {
+                eprintln!("Failed to emit LibreSpeed test result: {}", e);
+            }
+            emit_data_usage(&app_handle);
+        }
+
+        if let Err(e) = app_handle.emit("librespeed-test-complete", ()) {
+            eprintln!("Failed to emit LibreSpeed completion event: {}", e);
         }
     }).await;
 
@@ -154,6 +839,7 @@ pub async fn test_download_speed_all_dns(
 pub async fn test_docker_registries(
     image_name: String,
     timeout_seconds: u64,
+    platform: Option<String>,
     app_handle: AppHandle,
 ) -> Result<(), String> {
     let image_name = image_name.trim().to_string();
@@ -162,30 +848,26 @@ pub async fn test_docker_registries(
         return Err("Please enter a valid Docker image name".to_string());
     }
 
-    if !validate_docker_image_name(&image_name) {
-        return Err("Invalid Docker image name format".to_string());
-    }
+    ImageReference::parse(&image_name).map_err(|e| format!("Invalid Docker image name: {}", e))?;
 
-    println!("Starting Docker registry tests for image: {}", image_name);
+    let platform = match platform.as_deref().map(str::trim) {
+        Some(platform) if !platform.is_empty() => TargetPlatform::parse(platform).map_err(|e| e.to_string())?,
+        _ => TargetPlatform::host(),
+    };
+
+    println!("Starting Docker registry tests for image: {} ({})", image_name, platform);
 
     // Get registries list
     let docker_file_path = docker_config_path();
-    let registries = match read_docker_registries_file(&docker_file_path).await {
-        Ok(list) => list,
-        Err(_) => {
-            // Download config file if it doesn't exist
-            if let Err(e) = download_docker_config_file(DOCKER_CONFIG_URL, &docker_file_path).await
-            {
-                return Err(format!("Failed to download Docker registry config: {}", e));
-            }
-            match read_docker_registries_file(&docker_file_path).await {
-                Ok(list) => list,
-                Err(e) => {
-                    return Err(format!("Failed to read Docker registry config: {}", e));
-                }
-            }
+    // Download config file if it doesn't exist; an existing one may hold credentials, so never overwrite it
+    if !docker_file_path.exists() {
+        if let Err(e) = download_docker_config_file(DOCKER_CONFIG_URL, &docker_file_path).await {
+            return Err(format!("Failed to download Docker registry config: {}", e));
         }
-    };
+    }
+    let registries = read_docker_registries_file(&docker_file_path)
+        .await
+        .map_err(|e| format!("Failed to read Docker registry config {}: {}", docker_file_path.display(), e))?;
 
     println!(
         "Testing {} registries sequentially with {}s timeout",
@@ -193,9 +875,14 @@ pub async fn test_docker_registries(
         timeout_seconds
     );
 
+
+    // Ask credential helpers again on every run in case the user logged in since the last one
+    clear_credential_helper_cache();
+
     let image_name_for_task = image_name.clone();
     spawn_with_cleanup(image_name.clone(), move || async move {
-        for (index, registry) in registries.iter().enumerate() {
+        for (index, entry) in registries.iter().enumerate() {
+            let registry = &entry.registry;
             println!(
                 "Testing registry {}/{}: {}",
                 index + 1,
@@ -207,6 +894,8 @@ pub async fn test_docker_registries(
                 registry,
                 &image_name_for_task,
                 timeout_seconds,
+                entry.credentials.as_ref(),
+                &platform,
             )
             .await;
 
@@ -224,6 +913,7 @@ pub async fn test_docker_registries(
             } else {
                 println!("Successfully emitted result for {}", registry);
             }
+            emit_data_usage(&app_handle);
         }
 
         // All tests completed
@@ -242,8 +932,107 @@ pub async fn test_docker_registries(
 }
 
 #[tauri::command]
-pub async fn validate_docker_image(image_name: String) -> Result<bool, String> {
-    Ok(validate_docker_image_name(&image_name))
+pub async fn validate_docker_image(image_name: String) -> Result<(), String> {
+    // The error says what is wrong with the name
+    ImageReference::parse(&image_name).map(|_| ()).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_data_usage() -> DataUsage {
+    current_data_usage()
+}
+
+#[tauri::command]
+pub async fn set_data_budget(budget_bytes: Option<u64>) -> DataUsage {
+    crate::usage::set_data_budget(budget_bytes)
+}
+
+// Start a new usage session; tests never reset the counter themselves
+#[tauri::command]
+pub async fn reset_data_usage() -> DataUsage {
+    reset_session_usage();
+    current_data_usage()
+}
+
+// Starting again for a destination that has a partial file resumes it with a Range request
+#[tauri::command]
+pub async fn start_file_download(
+    url: String,
+    destination: String,
+    dns_server: Option<String>,
+    expected_sha256: Option<String>,
+    app_handle: AppHandle,
+) -> Result<(), String> {
+    let url = url.trim().to_string();
+    let destination = destination.trim().to_string();
+
+    if !url.starts_with("http://") && !url.starts_with("https://") {
+        return Err("URL must start with http:// or https://".to_string());
+    }
+
+    if destination.is_empty() {
+        return Err("Please choose a destination file".to_string());
+    }
+
+    let request = FileDownloadRequest {
+        url,
+        destination: destination.clone(),
+        dns_server: dns_server.filter(|dns_server| !dns_server.trim().is_empty()),
+        expected_sha256: expected_sha256.filter(|checksum| !checksum.trim().is_empty()),
+    };
+
+    let mut active_downloads = ACTIVE_DOWNLOADS.lock().unwrap();
+    if active_downloads.get(&destination).is_some_and(|handle| !handle.is_finished()) {
+        return Err("This file is already downloading".to_string());
+    }
+
+    let destination_for_cleanup = destination.clone();
+    let handle = tokio::spawn(async move {
+        let progress_handle = app_handle.clone();
+        let result = download_file(&request, move |progress| {
+            if let Err(e) = progress_handle.emit("file-download-progress", &progress) {
+                eprintln!("Failed to emit file download progress: {}", e);
+            }
+        })
+        .await;
+
+        match result {
+            Ok(result) => {
+                if let Err(e) = app_handle.emit("file-download-complete", &result) {
+                    eprintln!("Failed to emit file download completion: {}", e);
+                }
+            }
+            Err(e) => {
+                eprintln!("File download failed for {}: {}", request.destination, e);
+                let error = FileDownloadError {
+                    url: request.url.clone(),
+                    destination: request.destination.clone(),
+                    error_message: e.to_string(),
+                };
+                if let Err(e) = app_handle.emit("file-download-error", &error) {
+                    eprintln!("Failed to emit file download error: {}", e);
+                }
+            }
+        }
+
+        ACTIVE_DOWNLOADS.lock().unwrap().remove(&destination_for_cleanup);
+    });
+    active_downloads.insert(destination, handle);
+
+    Ok(())
+}
+
+// Stops the transfer but keeps the partial file so the download can be resumed later
+#[tauri::command]
+pub async fn pause_file_download(destination: String) -> Result<(), String> {
+    match ACTIVE_DOWNLOADS.lock().unwrap().remove(destination.trim()) {
+        Some(handle) => {
+            handle.abort();
+            println!("Paused file download: {}", destination);
+            Ok(())
+        }
+        None => Err("No active download for this file".to_string()),
+    }
 }
 
 #[tauri::command]
@@ -0,0 +1,127 @@
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, Instant};
+use tokio::net::TcpStream;
+
+use super::ResolvedHost;
+
+// Pause between connects so the probe doesn't look like a SYN flood
+const CONNECT_PROBE_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone, Copy)]
+pub struct ConnectLatencyStats {
+    pub attempts: u32,
+    pub successful_connects: u32,
+    pub min_rtt_ms: Option<f64>,
+    pub avg_rtt_ms: Option<f64>,
+    pub max_rtt_ms: Option<f64>,
+    // Mean difference between consecutive RTTs, as in RFC 3550
+    pub jitter_ms: Option<f64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ConnectLatencyResult {
+    pub dns_server: String,
+    pub host: String,
+    pub port: u16,
+    pub success: bool,
+    pub resolved_ip: Option<String>,
+    pub resolution_time_ms: Option<u64>,
+    pub attempts: u32,
+    pub successful_connects: u32,
+    // Fraction of connects that failed or timed out, 0.0 to 1.0
+    pub failure_rate: f64,
+    pub min_rtt_ms: Option<f64>,
+    pub avg_rtt_ms: Option<f64>,
+    pub max_rtt_ms: Option<f64>,
+    pub jitter_ms: Option<f64>,
+    pub error_message: Option<String>,
+    pub session_id: u64,
+}
+
+// Open and immediately close `attempts` TCP connections, timing each handshake
+pub async fn probe_connect_latency(addr: SocketAddr, attempts: u32, timeout: Duration) -> ConnectLatencyStats {
+    let mut rtts: Vec<f64> = Vec::new();
+
+    for attempt in 0..attempts {
+        if attempt > 0 {
+            tokio::time::sleep(CONNECT_PROBE_INTERVAL).await;
+        }
+
+        let connect_start = Instant::now();
+        match tokio::time::timeout(timeout, TcpStream::connect(addr)).await {
+            Ok(Ok(_)) => rtts.push(connect_start.elapsed().as_secs_f64() * 1000.0),
+            Ok(Err(e)) => println!("Connect {} to {} failed: {}", attempt + 1, addr, e),
+            Err(_) => println!("Connect {} to {} timed out", attempt + 1, addr),
+        }
+    }
+
+    let jitter_ms = if rtts.len() > 1 {
+        let total: f64 = rtts.windows(2).map(|pair| (pair[1] - pair[0]).abs()).sum();
+        Some(total / (rtts.len() - 1) as f64)
+    } else {
+        None
+    };
+
+    ConnectLatencyStats {
+        attempts,
+        successful_connects: rtts.len() as u32,
+        min_rtt_ms: rtts.iter().copied().reduce(f64::min),
+        avg_rtt_ms: (!rtts.is_empty()).then(|| rtts.iter().sum::<f64>() / rtts.len() as f64),
+        max_rtt_ms: rtts.iter().copied().reduce(f64::max),
+        jitter_ms,
+    }
+}
+
+pub fn connect_latency_result(
+    dns_server: String,
+    host: String,
+    port: u16,
+    resolved: &ResolvedHost,
+    ip: IpAddr,
+    stats: &ConnectLatencyStats,
+) -> ConnectLatencyResult {
+    let failed_connects = stats.attempts - stats.successful_connects;
+
+    ConnectLatencyResult {
+        dns_server,
+        host,
+        port,
+        success: stats.successful_connects > 0,
+        resolved_ip: Some(ip.to_string()),
+        resolution_time_ms: Some(resolved.resolution_time_ms),
+        attempts: stats.attempts,
+        successful_connects: stats.successful_connects,
+        failure_rate: if stats.attempts > 0 {
+            failed_connects as f64 / stats.attempts as f64
+        } else {
+            0.0
+        },
+        min_rtt_ms: stats.min_rtt_ms,
+        avg_rtt_ms: stats.avg_rtt_ms,
+        max_rtt_ms: stats.max_rtt_ms,
+        jitter_ms: stats.jitter_ms,
+        error_message: (stats.successful_connects == 0).then(|| "All connection attempts failed".to_string()),
+        session_id: 0,
+    }
+}
+
+pub fn failed_connect_latency_result(dns_server: String, host: String, port: u16, error: String) -> ConnectLatencyResult {
+    ConnectLatencyResult {
+        dns_server,
+        host,
+        port,
+        success: false,
+        resolved_ip: None,
+        resolution_time_ms: None,
+        attempts: 0,
+        successful_connects: 0,
+        failure_rate: 1.0,
+        min_rtt_ms: None,
+        avg_rtt_ms: None,
+        max_rtt_ms: None,
+        jitter_ms: None,
+        error_message: Some(error),
+        session_id: 0,
+    }
+}
@@ -0,0 +1,386 @@
+use futures_util::StreamExt;
+use reqwest::{Body, Client, Url};
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use super::upload::{random_payload, WrittenBytes, UPLOAD_CHUNK_SIZE};
+use super::{mbps, resolve_host};
+use crate::usage::{data_budget_exceeded, record_downloaded_bytes, DATA_BUDGET_EXCEEDED};
+
+// The LibreSpeed browser client scales measured bytes by this to account for HTTP/TCP overhead
+const OVERHEAD_COMPENSATION_FACTOR: f64 = 1.06;
+// Initial seconds of each phase that are discarded while TCP ramps up, as in the browser client
+const DOWNLOAD_GRACE_TIME: Duration = Duration::from_millis(1500);
+const UPLOAD_GRACE_TIME: Duration = Duration::from_secs(3);
+// Size of each upload request body, matching the browser client's 20 MB blob
+const UPLOAD_BODY_BYTES: usize = 20 * 1024 * 1024;
+const RESOLVE_TIMEOUT_SECONDS: u64 = 10;
+// A ping that takes longer than this is a failure, not a round-trip time
+const PING_TIMEOUT: Duration = Duration::from_secs(5);
+// A connection that stops sending fails the phase instead of hanging it
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Defaults mirror the LibreSpeed browser client so results are comparable
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct LibreSpeedOptions {
+    // Paths relative to the server URL
+    pub download_endpoint: String,
+    pub upload_endpoint: String,
+    pub ping_endpoint: String,
+    pub download_streams: usize,
+    pub upload_streams: usize,
+    // Megabytes requested per garbage download (`ckSize`)
+    pub chunk_size_mb: u32,
+    pub download_seconds: u64,
+    pub upload_seconds: u64,
+    pub ping_count: u32,
+}
+
+impl Default for LibreSpeedOptions {
+    fn default() -> Self {
+        Self {
+            download_endpoint: "backend/garbage.php".to_string(),
+            upload_endpoint: "backend/empty.php".to_string(),
+            ping_endpoint: "backend/empty.php".to_string(),
+            download_streams: 6,
+            upload_streams: 3,
+            chunk_size_mb: 100,
+            download_seconds: 15,
+            upload_seconds: 15,
+            ping_count: 10,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LibreSpeedResult {
+    pub dns_server: String,
+    pub server_url: String,
+    pub success: bool,
+    pub resolved_ip: Option<String>,
+    pub ping_ms: Option<f64>,
+    pub jitter_ms: Option<f64>,
+    pub download_mbps: Option<f64>,
+    pub upload_mbps: Option<f64>,
+    pub error_message: Option<String>,
+    pub session_id: u64,
+}
+
+// Unique query value so no cache between us and the server answers for it
+fn cache_buster() -> String {
+    format!("{:016x}", fastrand::u64(..))
+}
+
+fn endpoint_url(base: &Url, endpoint: &str) -> anyhow::Result<Url> {
+    let mut url = base.join(endpoint.trim_start_matches('/'))?;
+    url.query_pairs_mut().append_pair("r", &cache_buster());
+    Ok(url)
+}
+
+// Same statistics as the browser client: minimum RTT as ping and a smoothed jitter
+async fn measure_ping(client: &Client, base: &Url, options: &LibreSpeedOptions) -> anyhow::Result<(f64, f64)> {
+    // The first request opens the connection and isn't counted
+    client
+        .get(endpoint_url(base, &options.ping_endpoint)?)
+        .timeout(PING_TIMEOUT)
+        .send()
+        .await?
+        .bytes()
+        .await?;
+
+    let mut ping: Option<f64> = None;
+    let mut jitter = 0.0;
+    let mut previous: Option<f64> = None;
+    for _ in 0..options.ping_count.max(1) {
+        let request_start = Instant::now();
+        let response = client
+            .get(endpoint_url(base, &options.ping_endpoint)?)
+            .timeout(PING_TIMEOUT)
+            .send()
+            .await?;
+        let rtt = request_start.elapsed().as_secs_f64() * 1000.0;
+        if !response.status().is_success() {
+            return Err(anyhow::anyhow!("Ping endpoint returned HTTP {}", response.status()));
+        }
+        response.bytes().await?;
+
+        ping = Some(ping.map_or(rtt, |ping: f64| ping.min(rtt)));
+        if let Some(previous) = previous {
+            let instant_jitter = (rtt - previous).abs();
+            jitter = if instant_jitter > jitter {
+                jitter * 0.3 + instant_jitter * 0.7
+            } else {
+                jitter * 0.8 + instant_jitter * 0.2
+            };
+        }
+        previous = Some(rtt);
+    }
+
+    Ok((ping.unwrap_or_default(), jitter))
+}
+
+// Run `streams` workers until `duration` elapses and report Mbps over the part after `grace`
+async fn measure_throughput<F, Fut>(streams: usize, duration: Duration, grace: Duration, worker: F) -> anyhow::Result<f64>
+where
+    F: Fn(Arc<AtomicU64>) -> Fut,
+    Fut: Future<Output = anyhow::Result<()>>,
+{
+    let transferred = Arc::new(AtomicU64::new(0));
+    let phase_start = Instant::now();
+
+    let workers = futures::future::join_all((0..streams.max(1)).map(|_| worker(transferred.clone())));
+    let grace_snapshot = async {
+        tokio::time::sleep(grace).await;
+        (transferred.load(Ordering::Relaxed), Instant::now())
+    };
+
+    let (results, snapshot) = tokio::join!(
+        tokio::time::timeout(duration, workers),
+        tokio::time::timeout(duration, grace_snapshot)
+    );
+
+    let total = transferred.load(Ordering::Relaxed);
+    if total == 0 {
+        let error = results.ok().and_then(|results| results.into_iter().find_map(Result::err));
+        return Err(error.unwrap_or_else(|| anyhow::anyhow!("No data transferred")));
+    }
+
+    // A phase shorter than the grace time is measured from its start
+    let (bytes, seconds) = match snapshot {
+        Ok((grace_bytes, grace_instant)) => (total - grace_bytes, grace_instant.elapsed().as_secs_f64()),
+        Err(_) => (total, phase_start.elapsed().as_secs_f64()),
+    };
+    Ok(mbps(bytes, seconds) * OVERHEAD_COMPENSATION_FACTOR)
+}
+
+async fn download_worker(client: &Client, base: &Url, options: &LibreSpeedOptions, transferred: Arc<AtomicU64>) -> anyhow::Result<()> {
+    loop {
+        let mut url = endpoint_url(base, &options.download_endpoint)?;
+        url.query_pairs_mut().append_pair("ckSize", &options.chunk_size_mb.to_string());
+
+        let response = client.get(url).send().await?;
+        if !response.status().is_success() {
+            return Err(anyhow::anyhow!("Download endpoint returned HTTP {}", response.status()));
+        }
+
+        let mut stream = response.bytes_stream();
+        while let Some(chunk) = stream.next().await {
+            let len = chunk?.len() as u64;
+            record_downloaded_bytes(len);
+            transferred.fetch_add(len, Ordering::Relaxed);
+            if data_budget_exceeded() {
+                return Ok(());
+            }
+        }
+    }
+}
+
+async fn upload_worker(client: &Client, base: &Url, options: &LibreSpeedOptions, transferred: Arc<AtomicU64>, payload: Arc<Vec<u8>>) -> anyhow::Result<()> {
+    loop {
+        let written_bytes = WrittenBytes::new(transferred.clone());
+        let body_stream = {
+            let written_bytes = written_bytes.clone();
+            let payload = payload.clone();
+            futures::stream::unfold(0, move |start| {
+                let written_bytes = written_bytes.clone();
+                let payload = payload.clone();
+                async move {
+                    written_bytes.commit();
+                    if start >= UPLOAD_BODY_BYTES {
+                        return None;
+                    }
+                    let len = UPLOAD_CHUNK_SIZE.min(UPLOAD_BODY_BYTES - start);
+                    written_bytes.hand_out(len);
+                    Some((Ok::<_, std::io::Error>(payload[..len].to_vec()), start + len))
+                }
+            })
+        };
+
+        let response = client
+            .post(endpoint_url(base, &options.upload_endpoint)?)
+            .header(reqwest::header::CONTENT_TYPE, "application/octet-stream")
+            .body(Body::wrap_stream(body_stream))
+            .send()
+            .await?;
+        written_bytes.commit();
+        if !response.status().is_success() {
+            return Err(anyhow::anyhow!("Upload endpoint returned HTTP {}", response.status()));
+        }
+        if data_budget_exceeded() {
+            return Ok(());
+        }
+    }
+}
+
+async fn librespeed_with_custom_dns(
+    server_url: &str,
+    dns_server: &str,
+    options: &LibreSpeedOptions,
+    result: &mut LibreSpeedResult,
+) -> anyhow::Result<()> {
+    // Endpoints are joined onto the server URL, so it must end with a slash
+    let base = if server_url.ends_with('/') {
+        Url::parse(server_url)?
+    } else {
+        Url::parse(&format!("{}/", server_url))?
+    };
+    let host = base.host_str().ok_or_else(|| anyhow::anyhow!("Invalid host"))?;
+    let port = base
+        .port_or_known_default()
+        .ok_or_else(|| anyhow::anyhow!("Unsupported scheme"))?;
+
+    let resolved = resolve_host(host, dns_server, RESOLVE_TIMEOUT_SECONDS, false).await?;
+    result.resolved_ip = Some(resolved.ip.to_string());
+
+    let client = Client::builder()
+        .danger_accept_invalid_certs(true)
+        .connect_timeout(Duration::from_secs(10))
+        .read_timeout(READ_TIMEOUT)
+        .resolve(host, SocketAddr::new(resolved.ip, port))
+        .build()?;
+
+    let (ping_ms, jitter_ms) = measure_ping(&client, &base, options).await?;
+    println!("LibreSpeed ping via {}: {:.1}ms (jitter {:.1}ms)", dns_server, ping_ms, jitter_ms);
+    result.ping_ms = Some(ping_ms);
+    result.jitter_ms = Some(jitter_ms);
+
+    let download_mbps = measure_throughput(
+        options.download_streams,
+        Duration::from_secs(options.download_seconds),
+        DOWNLOAD_GRACE_TIME,
+        |transferred| download_worker(&client, &base, options, transferred),
+    )
+    .await?;
+    println!("LibreSpeed download via {}: {:.2} Mbps", dns_server, download_mbps);
+    result.download_mbps = Some(download_mbps);
+
+    if data_budget_exceeded() {
+        return Err(anyhow::anyhow!("Data budget exceeded, skipping upload"));
+    }
+
+    let payload = random_payload();
+    let upload_mbps = measure_throughput(
+        options.upload_streams,
+        Duration::from_secs(options.upload_seconds),
+        UPLOAD_GRACE_TIME,
+        |transferred| upload_worker(&client, &base, options, transferred, payload.clone()),
+    )
+    .await?;
+    println!("LibreSpeed upload via {}: {:.2} Mbps", dns_server, upload_mbps);
+    result.upload_mbps = Some(upload_mbps);
+
+    Ok(())
+}
+
+// Ping, download and upload against a LibreSpeed backend; phases that finished are kept on failure
+pub async fn test_librespeed_with_dns(
+    server_url: String,
+    dns_server: String,
+    options: &LibreSpeedOptions,
+    session_id: u64,
+) -> LibreSpeedResult {
+    let mut result = LibreSpeedResult {
+        dns_server,
+        server_url,
+        success: false,
+        resolved_ip: None,
+        ping_ms: None,
+        jitter_ms: None,
+        download_mbps: None,
+        upload_mbps: None,
+        error_message: None,
+        session_id,
+    };
+
+    if data_budget_exceeded() {
+        result.error_message = Some(DATA_BUDGET_EXCEEDED.to_string());
+        return result;
+    }
+
+    let server_url = result.server_url.clone();
+    let dns_server = result.dns_server.clone();
+    match librespeed_with_custom_dns(&server_url, &dns_server, options, &mut result).await {
+        Ok(()) => result.success = true,
+        Err(e) => result.error_message = Some(e.to_string()),
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns::test_server::serve;
+    use crate::dns::SYSTEM_DNS;
+    use std::io::Write;
+    use std::sync::atomic::AtomicUsize;
+
+    fn fast_options() -> LibreSpeedOptions {
+        LibreSpeedOptions {
+            download_streams: 2,
+            upload_streams: 2,
+            chunk_size_mb: 1,
+            download_seconds: 1,
+            upload_seconds: 1,
+            ping_count: 3,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn measures_against_a_local_backend() {
+        let uploads = Arc::new(AtomicUsize::new(0));
+        let uploaded = uploads.clone();
+        let port = serve(move |request, stream| match request.path.as_str() {
+            "/backend/garbage.php" => {
+                let size_mb: usize = request
+                    .query
+                    .split('&')
+                    .find_map(|pair| pair.strip_prefix("ckSize="))
+                    .and_then(|size| size.parse().ok())
+                    .unwrap_or(1);
+                write!(stream, "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n", size_mb * 1024 * 1024)?;
+                let block = vec![0u8; 1024 * 1024];
+                for _ in 0..size_mb {
+                    stream.write_all(&block)?;
+                }
+                Ok(())
+            }
+            "/backend/empty.php" => {
+                if request.method == "POST" && request.chunked && request.body_bytes > 0 {
+                    uploaded.fetch_add(1, Ordering::Relaxed);
+                }
+                stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n")
+            }
+            _ => stream.write_all(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n"),
+        });
+
+        let result = test_librespeed_with_dns(format!("http://127.0.0.1:{}", port), SYSTEM_DNS.to_string(), &fast_options(), 0).await;
+
+        assert!(result.success, "{:?}", result.error_message);
+        assert_eq!(result.resolved_ip.as_deref(), Some("127.0.0.1"));
+        assert!(result.ping_ms.is_some_and(|ping| ping >= 0.0));
+        assert!(result.download_mbps.is_some_and(|speed| speed > 0.0));
+        assert!(result.upload_mbps.is_some_and(|speed| speed > 0.0));
+        assert!(uploads.load(Ordering::Relaxed) > 0);
+    }
+
+    #[tokio::test]
+    async fn unanswered_ping_fails_instead_of_hanging() {
+        let port = serve(|_, _| {
+            std::thread::sleep(Duration::from_secs(30));
+            Ok(())
+        });
+
+        let started = Instant::now();
+        let result = test_librespeed_with_dns(format!("http://127.0.0.1:{}", port), SYSTEM_DNS.to_string(), &fast_options(), 0).await;
+
+        assert!(!result.success);
+        assert!(result.ping_ms.is_none());
+        assert!(started.elapsed() < PING_TIMEOUT * 2);
+    }
+}
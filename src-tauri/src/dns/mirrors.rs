@@ -0,0 +1,112 @@
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+
+use super::DownloadSpeedResult;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MirrorRanking {
+    // 1-based position; failed mirrors are ranked after every successful one
+    pub rank: usize,
+    pub url: String,
+    pub success: bool,
+    pub download_speed_mbps: f64,
+    pub transfer_speed_mbps: f64,
+    pub time_to_first_byte_ms: Option<u64>,
+    pub error_message: Option<String>,
+}
+
+// Throughputs in the same band count as equal, so TTFB decides between them
+const THROUGHPUT_BAND_RATIO: f64 = 0.05;
+
+// Fixed logarithmic bands keep the ordering transitive, unlike a pairwise "within 5%" check
+fn throughput_band(speed_mbps: f64) -> i64 {
+    if speed_mbps > 0.0 {
+        (speed_mbps.ln() / THROUGHPUT_BAND_RATIO.ln_1p()).floor() as i64
+    } else {
+        i64::MIN
+    }
+}
+
+// Order by body transfer throughput band, then by TTFB, then by exact throughput
+fn compare_mirrors(a: &DownloadSpeedResult, b: &DownloadSpeedResult) -> Ordering {
+    b.success
+        .cmp(&a.success)
+        .then(throughput_band(b.transfer_speed_mbps).cmp(&throughput_band(a.transfer_speed_mbps)))
+        .then(
+            a.time_to_first_byte_ms
+                .unwrap_or(u64::MAX)
+                .cmp(&b.time_to_first_byte_ms.unwrap_or(u64::MAX)),
+        )
+        .then(b.transfer_speed_mbps.total_cmp(&a.transfer_speed_mbps))
+}
+
+pub fn rank_mirror_results(results: &[DownloadSpeedResult]) -> Vec<MirrorRanking> {
+    let mut sorted: Vec<&DownloadSpeedResult> = results.iter().collect();
+    sorted.sort_by(|a, b| compare_mirrors(a, b));
+
+    sorted
+        .into_iter()
+        .enumerate()
+        .map(|(index, result)| MirrorRanking {
+            rank: index + 1,
+            url: result.url.clone(),
+            success: result.success,
+            download_speed_mbps: result.download_speed_mbps,
+            transfer_speed_mbps: result.transfer_speed_mbps,
+            time_to_first_byte_ms: result.time_to_first_byte_ms,
+            error_message: result.error_message.clone(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns::failed_download_result;
+
+    fn mirror(url: &str, transfer_speed_mbps: f64, time_to_first_byte_ms: Option<u64>) -> DownloadSpeedResult {
+        let mut result = failed_download_result("system".to_string(), url.to_string(), String::new(), 0);
+        result.success = true;
+        result.error_message = None;
+        result.transfer_speed_mbps = transfer_speed_mbps;
+        result.time_to_first_byte_ms = time_to_first_byte_ms;
+        result
+    }
+
+    fn ranked_urls(results: &[DownloadSpeedResult]) -> Vec<String> {
+        rank_mirror_results(results).into_iter().map(|ranking| ranking.url).collect()
+    }
+
+    #[test]
+    fn faster_mirror_wins_regardless_of_ttfb() {
+        let results = [mirror("slow", 50.0, Some(10)), mirror("fast", 100.0, Some(300))];
+        assert_eq!(ranked_urls(&results), vec!["fast", "slow"]);
+    }
+
+    #[test]
+    fn ttfb_breaks_ties_between_similar_throughputs() {
+        // 100.0 and 101.0 Mbps share a 5% band
+        assert_eq!(throughput_band(100.0), throughput_band(101.0));
+        let results = [mirror("laggy", 101.0, Some(400)), mirror("snappy", 100.0, Some(40))];
+        assert_eq!(ranked_urls(&results), vec!["snappy", "laggy"]);
+    }
+
+    #[test]
+    fn exact_throughput_decides_when_ttfb_is_equal() {
+        let results = [mirror("a", 100.0, Some(40)), mirror("b", 101.0, Some(40))];
+        assert_eq!(ranked_urls(&results), vec!["b", "a"]);
+    }
+
+    #[test]
+    fn failed_mirrors_rank_last() {
+        let failed = failed_download_result("system".to_string(), "broken".to_string(), "HTTP 404".to_string(), 0);
+        let results = [failed, mirror("slow", 1.0, None), mirror("fast", 90.0, Some(20))];
+
+        let rankings = rank_mirror_results(&results);
+        let urls: Vec<&str> = rankings.iter().map(|ranking| ranking.url.as_str()).collect();
+        assert_eq!(urls, vec!["fast", "slow", "broken"]);
+        assert_eq!(rankings.iter().map(|ranking| ranking.rank).collect::<Vec<_>>(), vec![1, 2, 3]);
+        assert!(!rankings[2].success);
+        assert_eq!(rankings[2].error_message.as_deref(), Some("HTTP 404"));
+    }
+}
@@ -1,14 +1,34 @@
+use std::collections::HashMap;
 use std::net::IpAddr;
 use std::time::{Duration, Instant};
-use trust_dns_resolver::config::{ResolverConfig, ResolverOpts, NameServerConfig, Protocol};
+use trust_dns_resolver::config::{LookupIpStrategy, ResolverConfig, ResolverOpts, NameServerConfig, Protocol};
 use trust_dns_resolver::TokioAsyncResolver;
 use serde::{Deserialize, Serialize};
 use reqwest::Client;
 use std::net::SocketAddr;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use url::Url;
 use reqwest::dns::{Resolve, Resolving, Name, Addrs};
 use futures_util::StreamExt;
+use tokio::net::TcpStream;
+use crate::usage::{data_budget_exceeded, record_downloaded_bytes, DATA_BUDGET_EXCEEDED};
+
+mod latency;
+mod librespeed;
+mod mirrors;
+mod quic;
+mod soak;
+#[cfg(test)]
+mod test_server;
+mod throttling;
+mod upload;
+pub use latency::{connect_latency_result, failed_connect_latency_result, probe_connect_latency, ConnectLatencyStats};
+pub use librespeed::{test_librespeed_with_dns, LibreSpeedOptions};
+pub use mirrors::rank_mirror_results;
+pub use soak::{summarize_soak, SoakSample};
+pub use upload::{test_upload_speed_with_dns, UploadProgress, UploadProgressCallback, UploadTestOptions};
+use quic::probe_quic;
+use throttling::{detect_throttling, ThrottleDetection, ThroughputSample, THROUGHPUT_SAMPLE_INTERVAL};
 
 // Original DNS servers constants
 pub const DNS_SERVERS: &[&str] = &[
@@ -99,6 +119,9 @@ pub const DNS_SERVERS: &[&str] = &[
     "149.112.112.10",
 ];
 
+// Pseudo server name that routes resolution through the operating system resolver
+pub const SYSTEM_DNS: &str = "system";
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub enum HttpStatus {
     Success,
@@ -126,12 +149,189 @@ pub struct DownloadSpeedResult {
     pub success: bool,
     pub download_speed_mbps: f64,
     pub downloaded_bytes: u64,
+    // From the first request on an open connection; DNS, connect and TLS are reported separately
     pub test_duration_seconds: f64,
     pub error_message: Option<String>,
     pub resolution_time_ms: Option<u64>,
+    pub connect_time_ms: Option<u64>,
+    pub tls_handshake_time_ms: Option<u64>,
+    pub time_to_first_byte_ms: Option<u64>,
+    // Throughput of the body transfer alone, excluding DNS, connect, TLS and TTFB
+    pub transfer_speed_mbps: f64,
+    pub transfer_duration_seconds: f64,
+    pub resolved_ip: Option<String>,
+    // DNS server whose download produced these numbers when the measurement is shared by servers resolving to the same address
+    pub measured_via_dns_server: Option<String>,
+    pub max_bytes: Option<u64>,
+    pub stop_reason: Option<DownloadStopReason>,
+    // Parallel range connections actually used; 1 when the server lacks range support
+    pub connections: usize,
+    // One entry per measured address; only the first address is measured unless all addresses are requested
+    pub address_results: Vec<AddressSpeedResult>,
+    pub best_ipv4_speed_mbps: Option<f64>,
+    pub best_ipv6_speed_mbps: Option<f64>,
+    // Set when throughput dropped and stayed down partway through, e.g. an ISP throttling after a burst
+    pub throttled_after_bytes: Option<u64>,
+    pub throttled_after_seconds: Option<f64>,
+    pub pre_throttle_speed_mbps: Option<f64>,
+    pub post_throttle_speed_mbps: Option<f64>,
+    // Version actually used for the transfer, e.g. "HTTP/1.1" or "HTTP/2.0"
+    pub http_version: Option<String>,
+    // Server advertised h3 in its Alt-Svc header
+    pub http3_advertised: Option<bool>,
+    // Only probed when HTTP/3 is requested: a QUIC server answered on the Alt-Svc h3 port, or UDP/443
+    pub quic_reachable: Option<bool>,
+    // Explains when the transfer didn't use the requested protocol, e.g. HTTP/3 is probed but not downloaded over
+    pub protocol_note: Option<String>,
+    // Fairness settings this result was measured with
+    pub randomized_order: bool,
+    pub warm_up: bool,
+    // Mode actually applied, which is None when RandomRange was requested but the server ignored ranges
+    pub cache_busting: CacheBusting,
+    // 1-based place in the run's test order
+    pub test_position: Option<usize>,
+    pub session_id: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum IpVersion {
+    V4,
+    V6,
+}
+
+impl IpVersion {
+    fn of(ip: &IpAddr) -> Self {
+        match ip {
+            IpAddr::V4(_) => IpVersion::V4,
+            IpAddr::V6(_) => IpVersion::V6,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AddressSpeedResult {
+    pub address: String,
+    pub ip_version: IpVersion,
+    pub success: bool,
+    pub download_speed_mbps: f64,
+    pub transfer_speed_mbps: f64,
+    pub downloaded_bytes: u64,
+    pub connect_time_ms: Option<u64>,
+    pub time_to_first_byte_ms: Option<u64>,
+    pub error_message: Option<String>,
+}
+
+// Per-download settings passed through from the frontend
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct DownloadTestOptions {
+    pub max_bytes: Option<u64>,
+    // Open this many HTTP Range connections to the resolved address instead of a single stream
+    pub connections: Option<usize>,
+    // Measure every address in the DNS answer (A and AAAA) instead of only the first; they share the time limit
+    pub test_all_addresses: bool,
+    // With test_all_addresses, only measure the N addresses that connect fastest
+    pub max_addresses: Option<usize>,
+    pub protocol: HttpProtocol,
+    // Extra request headers for targets behind auth, e.g. artifact stores or API-served release assets
+    pub headers: HashMap<String, String>,
+    pub auth: Option<DownloadAuth>,
+    // Raw `Cookie` header value, e.g. "session=abc; token=xyz"
+    pub cookies: Option<String>,
+    pub user_agent: Option<String>,
+    // Test servers in a random order so no server always gets the cold (or warm) link
+    pub randomize_order: bool,
+    // Send a throwaway request first so the measurement doesn't start on a cold connection
+    pub warm_up: bool,
+    pub cache_busting: CacheBusting,
+}
+
+// How to keep CDN caches warmed by earlier servers from favouring later ones
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+pub enum CacheBusting {
+    #[default]
+    None,
+    // Append a random `_cb` query parameter to every test URL
+    QueryParam,
+    // Download a randomly placed byte range instead of the start of the file
+    RandomRange,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub enum DownloadAuth {
+    Basic { username: String, password: Option<String> },
+    Bearer { token: String },
+}
+
+// Keep credentials out of the logs
+impl std::fmt::Debug for DownloadAuth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DownloadAuth::Basic { username, .. } => write!(f, "Basic({}, ***)", username),
+            DownloadAuth::Bearer { .. } => write!(f, "Bearer(***)"),
+        }
+    }
+}
+
+// HTTP version to use for download tests
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+pub enum HttpProtocol {
+    // Whatever ALPN negotiates, usually HTTP/2 over TLS when the server offers it
+    #[default]
+    Auto,
+    Http1,
+    // Negotiated through ALPN, so it needs an https URL; fails if the server doesn't pick h2
+    Http2,
+    // The HTTP client can't speak HTTP/3 yet, so this probes QUIC and downloads over the best TCP protocol
+    Http3,
+}
+
+// Which condition ended a download test
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum DownloadStopReason {
+    Completed,
+    TimeLimit,
+    ByteLimit,
+    DataBudget,
+}
+
+// Periodic throughput sample emitted while a download test is running
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DownloadProgress {
+    pub dns_server: String,
+    pub url: String,
+    pub address: String,
+    pub downloaded_bytes: u64,
+    pub instant_speed_mbps: f64,
+    pub average_speed_mbps: f64,
+    pub elapsed_seconds: f64,
     pub session_id: u64,
 }
 
+pub type ProgressCallback = Arc<dyn Fn(DownloadProgress) + Send + Sync>;
+
+const MAX_DOWNLOAD_CONNECTIONS: usize = 16;
+
+const QUIC_PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+// Most a warm-up request may read before it's dropped
+const WARM_UP_MAX_BYTES: u64 = 64 * 1024;
+// Cap on each setup step (connect and TLS probes, warm-up, pre-connect) so a dead address fails fast
+const SETUP_STEP_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Connect timeout used when ranking addresses for max_addresses
+const ADDRESS_RANKING_TIMEOUT: Duration = Duration::from_secs(3);
+
+// How often progress samples are reported from the download loop
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+fn mbps(bytes: u64, seconds: f64) -> f64 {
+    if seconds > 0.0 {
+        (bytes as f64 * 8.0) / (seconds * 1_000_000.0)
+    } else {
+        0.0
+    }
+}
+
 
 pub fn ensure_https_url(input: &str) -> Option<Url> {
     let clean = input.trim().replace("http://", "").replace("https://", "");
@@ -291,7 +491,25 @@ fn ensure_https(domain: &str) -> String {
     result
 }
 
-async fn resolve_host_with_dns(host: &str, dns_server: &str) -> anyhow::Result<IpAddr> {
+// Resolve through the operating system's configured resolver instead of a specific server
+async fn resolve_host_with_system(host: &str, include_ipv6: bool) -> anyhow::Result<Vec<IpAddr>> {
+    let mut addresses: Vec<IpAddr> = Vec::new();
+    for addr in tokio::net::lookup_host((host, 0)).await? {
+        if (include_ipv6 || addr.is_ipv4()) && !addresses.contains(&addr.ip()) {
+            addresses.push(addr.ip());
+        }
+    }
+    if addresses.is_empty() {
+        return Err(anyhow::anyhow!("No IP found for host"));
+    }
+    Ok(addresses)
+}
+
+async fn resolve_host_with_dns(host: &str, dns_server: &str, include_ipv6: bool) -> anyhow::Result<Vec<IpAddr>> {
+    if dns_server == SYSTEM_DNS {
+        return resolve_host_with_system(host, include_ipv6).await;
+    }
+
     let socket_addr: SocketAddr = format!("{}:53", dns_server).parse()?;
     let nameserver = NameServerConfig {
         socket_addr,
@@ -307,129 +525,986 @@ async fn resolve_host_with_dns(host: &str, dns_server: &str) -> anyhow::Result<I
     let mut resolver_opts = ResolverOpts::default();
     resolver_opts.timeout = Duration::from_secs(5); // 5 second timeout for DNS resolution
     resolver_opts.attempts = 2; // 2 attempts max
+    if include_ipv6 {
+        // Ask for AAAA records too so IPv4 and IPv6 endpoints can be compared
+        resolver_opts.ip_strategy = LookupIpStrategy::Ipv4AndIpv6;
+    }
     
     let resolver = TokioAsyncResolver::tokio(resolver_config, resolver_opts);
 
     let response = resolver.lookup_ip(host).await?;
-    response
-        .iter()
-        .next()
-        .ok_or_else(|| anyhow::anyhow!("No IP found for host"))
+    let addresses: Vec<IpAddr> = response.iter().collect();
+    if addresses.is_empty() {
+        return Err(anyhow::anyhow!("No IP found for host"));
+    }
+    Ok(addresses)
 }
 
-async fn download_with_custom_dns(url: &str, dns_ip: &str, timeout_seconds: u64, _session_id: u64) -> anyhow::Result<DownloadSpeedResult> {
-    println!("Starting download test: {} with DNS: {}", url, dns_ip);
-    
-    // Start the overall timer from the beginning (includes DNS resolution + connection + download)
-    let overall_start = Instant::now();
-    let timeout_duration = std::time::Duration::from_secs(timeout_seconds);
-    
-    let resolution_start = Instant::now();
-    
+// Time a bare TCP connect and, for HTTPS, a TLS handshake on a dedicated probe connection.
+// reqwest doesn't expose per-phase timings, so these are measured separately against the same address
+// and kept out of the speed figures.
+async fn measure_connection_phases(
+    host: &str,
+    socket_addr: SocketAddr,
+    use_tls: bool,
+    timeout: Duration,
+) -> (Option<u64>, Option<u64>) {
+    let connect_start = Instant::now();
+    let stream = match tokio::time::timeout(timeout, TcpStream::connect(socket_addr)).await {
+        Ok(Ok(stream)) => stream,
+        Ok(Err(e)) => {
+            println!("Connect probe to {} failed: {}", socket_addr, e);
+            return (None, None);
+        }
+        Err(_) => {
+            println!("Connect probe to {} timed out", socket_addr);
+            return (None, None);
+        }
+    };
+    let connect_time_ms = connect_start.elapsed().as_millis() as u64;
+
+    if !use_tls {
+        return (Some(connect_time_ms), None);
+    }
+
+    let connector = match native_tls::TlsConnector::builder()
+        .danger_accept_invalid_certs(true)
+        .build()
+    {
+        Ok(connector) => tokio_native_tls::TlsConnector::from(connector),
+        Err(e) => {
+            println!("Failed to build TLS connector: {}", e);
+            return (Some(connect_time_ms), None);
+        }
+    };
+
+    let tls_start = Instant::now();
+    let tls_handshake_time_ms = match tokio::time::timeout(timeout, connector.connect(host, stream)).await {
+        Ok(Ok(_)) => Some(tls_start.elapsed().as_millis() as u64),
+        Ok(Err(e)) => {
+            println!("TLS handshake probe with {} failed: {}", socket_addr, e);
+            None
+        }
+        Err(_) => {
+            println!("TLS handshake probe with {} timed out", socket_addr);
+            None
+        }
+    };
+
+    (Some(connect_time_ms), tls_handshake_time_ms)
+}
+
+// Addresses a download host resolved to through a specific DNS server
+#[derive(Debug, Clone)]
+pub struct ResolvedHost {
+    // First address in the answer, used when only one address is measured
+    pub ip: IpAddr,
+    pub addresses: Vec<IpAddr>,
+    pub resolution_time_ms: u64,
+}
+
+// Resolve the host part of a download URL through the given DNS server
+pub async fn resolve_download_host(
+    url: &str,
+    dns_ip: &str,
+    timeout_seconds: u64,
+    include_ipv6: bool,
+) -> anyhow::Result<ResolvedHost> {
     let parsed_url = reqwest::Url::parse(url)?;
     let host = parsed_url.host_str().ok_or_else(|| anyhow::anyhow!("Invalid host"))?;
-    
-    println!("Parsed URL - host: {}, scheme: {}", host, parsed_url.scheme());
+    resolve_host(host, dns_ip, timeout_seconds, include_ipv6).await
+}
+
+pub async fn resolve_host(
+    host: &str,
+    dns_ip: &str,
+    timeout_seconds: u64,
+    include_ipv6: bool,
+) -> anyhow::Result<ResolvedHost> {
+    let resolution_start = Instant::now();
 
-    // Resolve host with custom DNS with timeout
     println!("Resolving {} using DNS {}", host, dns_ip);
-    
+
     // Apply timeout to DNS resolution
-    let resolved_ip = tokio::time::timeout(
-        timeout_duration,
-        resolve_host_with_dns(host, dns_ip)
+    let addresses = tokio::time::timeout(
+        Duration::from_secs(timeout_seconds),
+        resolve_host_with_dns(host, dns_ip, include_ipv6)
     ).await
     .map_err(|_| anyhow::anyhow!("DNS resolution timed out after {} seconds", timeout_seconds))?
     .map_err(|e| anyhow::anyhow!("DNS resolution failed: {}", e))?;
-    
+
     let resolution_time_ms = resolution_start.elapsed().as_millis() as u64;
-    println!("DNS resolution successful: {} -> {} ({}ms)", host, resolved_ip, resolution_time_ms);
+    println!("DNS resolution successful: {} -> {:?} ({}ms)", host, addresses, resolution_time_ms);
+
+    Ok(ResolvedHost { ip: addresses[0], addresses, resolution_time_ms })
+}
+
+fn cache_busted_url(url: &reqwest::Url) -> String {
+    let mut busted = url.clone();
+    busted
+        .query_pairs_mut()
+        .append_pair("_cb", &format!("{:016x}", fastrand::u64(..)));
+    busted.to_string()
+}
+
+fn with_headers_and_auth(
+    mut request: reqwest::RequestBuilder,
+    headers: &HashMap<String, String>,
+    auth: Option<&DownloadAuth>,
+) -> reqwest::RequestBuilder {
+    for (name, value) in headers {
+        request = request.header(name.as_str(), value.as_str());
+    }
+    match auth {
+        Some(DownloadAuth::Basic { username, password }) => request.basic_auth(username, password.as_ref()),
+        Some(DownloadAuth::Bearer { token }) => request.bearer_auth(token),
+        None => request,
+    }
+}
+
+// Apply the caller's headers, auth and cookies
+fn with_download_options(request: reqwest::RequestBuilder, options: &DownloadTestOptions) -> reqwest::RequestBuilder {
+    let mut request = with_headers_and_auth(request, &options.headers, options.auth.as_ref());
+    if let Some(cookies) = &options.cookies {
+        request = request.header(reqwest::header::COOKIE, cookies.as_str());
+    }
+    request
+}
+
+// GET request with the caller's headers, auth and cookies applied
+fn download_request(client: &Client, url: &str, options: &DownloadTestOptions) -> reqwest::RequestBuilder {
+    with_download_options(client.get(url), options)
+}
+
+// Shared byte counter and stop conditions for one measurement, fed by one or more streams
+struct TransferMeter<'a> {
+    url: &'a str,
+    dns_ip: &'a str,
+    address: IpAddr,
+    session_id: u64,
+    on_progress: Option<&'a ProgressCallback>,
+    // Speed is measured from `start`; the time limit ends at `deadline`
+    start: Instant,
+    deadline: Instant,
+    max_bytes: Option<u64>,
+    state: Mutex<TransferState>,
+}
 
-    // Check if we still have time left after DNS resolution
-    if overall_start.elapsed() >= timeout_duration {
-        return Err(anyhow::anyhow!("Operation timed out during DNS resolution"));
+struct TransferState {
+    downloaded_bytes: u64,
+    last_progress_time: Instant,
+    last_progress_bytes: u64,
+    stop_reason: Option<DownloadStopReason>,
+    // Fixed-width byte counts used to spot throttling after an initial burst
+    samples: Vec<ThroughputSample>,
+    sample_start: Instant,
+    sample_bytes: u64,
+}
+
+impl<'a> TransferMeter<'a> {
+    fn new(context: &DownloadContext<'a>, address: IpAddr, start: Instant, deadline: Instant) -> Self {
+        Self {
+            url: context.url,
+            dns_ip: context.dns_ip,
+            address,
+            session_id: context.session_id,
+            on_progress: context.on_progress,
+            start,
+            deadline,
+            max_bytes: context.options.max_bytes,
+            state: Mutex::new(TransferState {
+                downloaded_bytes: 0,
+                last_progress_time: Instant::now(),
+                last_progress_bytes: 0,
+                stop_reason: None,
+                samples: Vec::new(),
+                sample_start: Instant::now(),
+                sample_bytes: 0,
+            }),
+        }
     }
 
+    fn downloaded_bytes(&self) -> u64 {
+        self.state.lock().unwrap().downloaded_bytes
+    }
+
+    // Analyse the collected samples, including the final partial bucket
+    fn throttling(&self) -> Option<ThrottleDetection> {
+        let state = self.state.lock().unwrap();
+        let mut samples = state.samples.clone();
+        if state.sample_bytes > 0 {
+            samples.push(ThroughputSample {
+                duration_seconds: state.sample_start.elapsed().as_secs_f64(),
+                bytes: state.sample_bytes,
+            });
+        }
+        detect_throttling(&samples)
+    }
+
+    fn stop_reason(&self) -> Option<DownloadStopReason> {
+        self.state.lock().unwrap().stop_reason
+    }
+
+    // Keep the first reason so every stream reports the same one
+    fn stop(&self, reason: DownloadStopReason) {
+        let mut state = self.state.lock().unwrap();
+        state.stop_reason.get_or_insert(reason);
+    }
+
+    fn should_stop(&self) -> bool {
+        if Instant::now() >= self.deadline {
+            self.stop(DownloadStopReason::TimeLimit);
+        }
+        self.stop_reason().is_some()
+    }
+
+    // Count a received chunk, report progress when due and return false once a limit is reached
+    fn record_chunk(&self, len: u64) -> bool {
+        record_downloaded_bytes(len);
+
+        let mut state = self.state.lock().unwrap();
+        state.downloaded_bytes += len;
+        state.sample_bytes += len;
+
+        if state.sample_start.elapsed() >= THROUGHPUT_SAMPLE_INTERVAL {
+            let sample = ThroughputSample {
+                duration_seconds: state.sample_start.elapsed().as_secs_f64(),
+                bytes: state.sample_bytes,
+            };
+            state.samples.push(sample);
+            state.sample_start = Instant::now();
+            state.sample_bytes = 0;
+        }
+
+        if data_budget_exceeded() {
+            state.stop_reason.get_or_insert(DownloadStopReason::DataBudget);
+        } else if self.max_bytes.is_some_and(|max_bytes| state.downloaded_bytes >= max_bytes) {
+            state.stop_reason.get_or_insert(DownloadStopReason::ByteLimit);
+        }
+
+        let progress = if state.last_progress_time.elapsed() >= PROGRESS_INTERVAL {
+            let elapsed = self.start.elapsed().as_secs_f64();
+            let progress = DownloadProgress {
+                dns_server: self.dns_ip.to_string(),
+                url: self.url.to_string(),
+                address: self.address.to_string(),
+                downloaded_bytes: state.downloaded_bytes,
+                instant_speed_mbps: mbps(
+                    state.downloaded_bytes - state.last_progress_bytes,
+                    state.last_progress_time.elapsed().as_secs_f64(),
+                ),
+                average_speed_mbps: mbps(state.downloaded_bytes, elapsed),
+                elapsed_seconds: elapsed,
+                session_id: self.session_id,
+            };
+            state.last_progress_time = Instant::now();
+            state.last_progress_bytes = state.downloaded_bytes;
+            Some(progress)
+        } else {
+            None
+        };
+        let keep_going = state.stop_reason.is_none();
+        // Emit without the lock so other segments aren't blocked behind the event
+        drop(state);
+
+        if let (Some(progress), Some(on_progress)) = (progress, self.on_progress) {
+            on_progress(progress);
+        }
+        keep_going
+    }
+}
+
+// Read a response body into the meter until it ends or a limit is reached
+async fn consume_response(response: reqwest::Response, meter: &TransferMeter<'_>) -> anyhow::Result<()> {
+    let mut stream = response.bytes_stream();
+
+    while let Some(chunk_result) = stream.next().await {
+        if meter.should_stop() {
+            break;
+        }
+
+        let chunk = match chunk_result {
+            Ok(chunk) => chunk,
+            // The client timeout can fire mid-stream, which still counts as reaching the time limit
+            Err(e) if e.is_timeout() && meter.downloaded_bytes() > 0 => {
+                meter.stop(DownloadStopReason::TimeLimit);
+                break;
+            }
+            Err(e) => return Err(anyhow::anyhow!("Stream error: {}", e)),
+        };
+
+        if !meter.record_chunk(chunk.len() as u64) {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+// Read and count a warm-up or pre-connect response, giving up after WARM_UP_MAX_BYTES in case the server ignored the range.
+// Returns true when the whole body arrived, which leaves the connection open for reuse.
+async fn consume_warm_up(response: reqwest::Response) -> bool {
+    let mut stream = response.bytes_stream();
+    let mut received = 0u64;
+    loop {
+        match stream.next().await {
+            Some(Ok(chunk)) => {
+                record_downloaded_bytes(chunk.len() as u64);
+                received += chunk.len() as u64;
+                if received >= WARM_UP_MAX_BYTES {
+                    return false;
+                }
+            }
+            Some(Err(_)) => return false,
+            None => return true,
+        }
+    }
+}
+
+// Total size from a `Content-Range: bytes 0-0/12345` header
+fn content_range_total(response: &reqwest::Response) -> Option<u64> {
+    response
+        .headers()
+        .get(reqwest::header::CONTENT_RANGE)?
+        .to_str()
+        .ok()?
+        .rsplit('/')
+        .next()?
+        .parse()
+        .ok()
+}
+
+// First and last byte from a `Content-Range: bytes 100-199/12345` header
+fn content_range_bounds(response: &reqwest::Response) -> Option<(u64, u64)> {
+    parse_content_range_bounds(response.headers().get(reqwest::header::CONTENT_RANGE)?.to_str().ok()?)
+}
+
+fn parse_content_range_bounds(value: &str) -> Option<(u64, u64)> {
+    let (start, end) = value.strip_prefix("bytes ")?.split('/').next()?.split_once('-')?;
+    let (start, end) = (start.trim().parse().ok()?, end.trim().parse().ok()?);
+    (start <= end).then_some((start, end))
+}
+
+// Port of the first h3 entry in Alt-Svc, e.g. `h3=":443"; ma=86400`
+fn alt_svc_h3_port(response: &reqwest::Response) -> Option<u16> {
+    response
+        .headers()
+        .get_all(reqwest::header::ALT_SVC)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .find_map(parse_alt_svc_h3_port)
+}
+
+fn parse_alt_svc_h3_port(value: &str) -> Option<u16> {
+    value
+        .split(',')
+        .filter(|entry| entry.trim_start().starts_with("h3"))
+        .find_map(|entry| {
+            let authority = entry.split(';').next()?.split_once('=')?.1.trim().trim_matches('"');
+            authority.rsplit_once(':')?.1.parse().ok()
+        })
+}
+
+// Split `total` bytes into at most `connections` inclusive ranges
+fn split_ranges(total: u64, connections: usize) -> Vec<(u64, u64)> {
+    let segment_len = total.div_ceil(connections as u64).max(1);
+    (0..total)
+        .step_by(segment_len as usize)
+        .map(|start| (start, (start + segment_len).min(total) - 1))
+        .collect()
+}
+
+// Settings shared by every address measured in one download test
+struct DownloadContext<'a> {
+    url: &'a str,
+    // `url` with any cache-busting applied; used for the measured requests
+    request_url: &'a str,
+    host: &'a str,
+    port: u16,
+    dns_ip: &'a str,
+    options: &'a DownloadTestOptions,
+    session_id: u64,
+    on_progress: Option<&'a ProgressCallback>,
+}
+
+// Raw numbers from downloading through one resolved address
+struct AddressMeasurement {
+    connect_time_ms: Option<u64>,
+    tls_handshake_time_ms: Option<u64>,
+    time_to_first_byte_ms: u64,
+    downloaded_bytes: u64,
+    elapsed_seconds: f64,
+    transfer_duration_seconds: f64,
+    stop_reason: DownloadStopReason,
+    connections: usize,
+    throttling: Option<ThrottleDetection>,
+    http_version: String,
+    http3_advertised: bool,
+    quic_reachable: Option<bool>,
+    // RandomRange falls back to None when the server sent the whole body instead of a range
+    cache_busting: CacheBusting,
+}
+
+impl AddressMeasurement {
+    fn speed_mbps(&self) -> f64 {
+        mbps(self.downloaded_bytes, self.elapsed_seconds)
+    }
+
+    fn transfer_speed_mbps(&self) -> f64 {
+        mbps(self.downloaded_bytes, self.transfer_duration_seconds)
+    }
+}
+
+// Download through a single address for up to `time_limit`, setup included.
+// The connection is opened and any warm-up done before the clock starts, so speed and TTFB exclude them.
+async fn measure_address(
+    context: &DownloadContext<'_>,
+    ip: IpAddr,
+    time_limit: Duration,
+) -> anyhow::Result<AddressMeasurement> {
+    let DownloadContext { url, request_url, host, port, options, .. } = *context;
+    let requested_connections = options.connections.unwrap_or(1).clamp(1, MAX_DOWNLOAD_CONNECTIONS);
+    let random_range = options.cache_busting == CacheBusting::RandomRange;
+    // Learn the body size with a one-byte range first when the download will be split or offset
+    let probe_ranges = requested_connections > 1 || random_range;
+    let socket_addr = SocketAddr::new(ip, port);
+
+    if time_limit.is_zero() {
+        return Err(anyhow::anyhow!("Operation timed out before HTTP request"));
+    }
+    let deadline = Instant::now() + time_limit;
+    let remaining = || deadline.saturating_duration_since(Instant::now());
+    let setup_timeout = || SETUP_STEP_TIMEOUT.min(remaining());
+
+    let (connect_time_ms, tls_handshake_time_ms) =
+        measure_connection_phases(host, socket_addr, port == 443, setup_timeout()).await;
+    println!(
+        "Connection phases for {}: connect={:?}ms, tls={:?}ms",
+        socket_addr, connect_time_ms, tls_handshake_time_ms
+    );
+    if connect_time_ms.is_none() {
+        return Err(anyhow::anyhow!("Could not connect to {}", socket_addr));
+    }
+
+    // Each segment gets its own client so it runs over a separate connection
+    let build_client = || {
+        let mut builder = Client::builder()
+            .danger_accept_invalid_certs(true)
+            .timeout(time_limit)
+            .resolve(host, socket_addr);
+        if let Some(user_agent) = &options.user_agent {
+            builder = builder.user_agent(user_agent.as_str());
+        }
+        match options.protocol {
+            HttpProtocol::Http1 => builder.http1_only(),
+            // h2 is offered through ALPN; the negotiated version is checked on the first response
+            HttpProtocol::Auto | HttpProtocol::Http2 | HttpProtocol::Http3 => builder,
+        }
+        .build()
+    };
+    let client = build_client()?;
+
+    // Warm-up and pre-connect use the plain URL so a cache-busted measurement doesn't find its own URL already cached
+    let warmed_up = options.warm_up
+        && match download_request(&client, url, options)
+            .header(reqwest::header::RANGE, "bytes=0-0")
+            .timeout(setup_timeout())
+            .send()
+            .await
+        {
+            Ok(response) => consume_warm_up(response).await,
+            Err(e) => {
+                println!("Warm-up request to {} failed: {}", socket_addr, e);
+                false
+            }
+        };
+    if !warmed_up {
+        // A bodiless HEAD only opens the connection for the measured request; anything it does return is counted
+        match with_download_options(client.head(url), options).timeout(setup_timeout()).send().await {
+            Ok(response) => {
+                consume_warm_up(response).await;
+            }
+            Err(e) => println!("Pre-connect request to {} failed: {}", socket_addr, e),
+        }
+    }
+
+    // Setup came out of the time limit, but the measurement itself starts here
+    let request_start = Instant::now();
+    if remaining().is_zero() {
+        return Err(anyhow::anyhow!("Connection setup to {} used up the time limit", socket_addr));
+    }
+    let first_request = if probe_ranges {
+        download_request(&client, request_url, options).header(reqwest::header::RANGE, "bytes=0-0")
+    } else {
+        download_request(&client, request_url, options)
+    };
+    let first_response = first_request.timeout(remaining()).send().await
+        .map_err(|e| anyhow::anyhow!("HTTP request failed: {}", e))?;
+    let time_to_first_byte_ms = request_start.elapsed().as_millis() as u64;
+    let http_version = format!("{:?}", first_response.version());
+    let http3_advertised = first_response
+        .headers()
+        .get_all(reqwest::header::ALT_SVC)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .any(|value| value.split(',').any(|entry| entry.trim_start().starts_with("h3")));
+    let h3_port = alt_svc_h3_port(&first_response);
+    println!("Negotiated {} with {} (h3 advertised: {})", http_version, socket_addr, http3_advertised);
+
+    if options.protocol == HttpProtocol::Http2 && first_response.version() != reqwest::Version::HTTP_2 {
+        return Err(anyhow::anyhow!("Server negotiated {} instead of HTTP/2", http_version));
+    }
+
+    let meter = TransferMeter::new(context, ip, request_start, deadline);
+
+    // Segment only when the server honours ranges; otherwise the first response is the whole body
+    let range_total = if probe_ranges
+        && first_response.status() == reqwest::StatusCode::PARTIAL_CONTENT
+    {
+        content_range_total(&first_response).filter(|&total| total > 1)
+    } else {
+        None
+    };
+
+    let cache_busting = match options.cache_busting {
+        CacheBusting::RandomRange if range_total.is_none() => CacheBusting::None,
+        cache_busting => cache_busting,
+    };
+
+    let transfer_start = Instant::now();
+    let (responses, connections) = match range_total {
+        Some(total) => {
+            let (offset, length) = if random_range {
+                // Without a size limit read half the file so the window still has room to move
+                let length = options.max_bytes.unwrap_or(total / 2).clamp(1, total);
+                (fastrand::u64(..=total - length), length)
+            } else {
+                (0, options.max_bytes.map_or(total, |max_bytes| total.min(max_bytes)))
+            };
+            let ranges: Vec<(u64, u64)> = split_ranges(length, requested_connections)
+                .into_iter()
+                .map(|(start, end)| (start + offset, end + offset))
+                .collect();
+            println!(
+                "Server supports ranges, downloading {} bytes from offset {} over {} connections",
+                length,
+                offset,
+                ranges.len()
+            );
+
+            let responses = futures::future::try_join_all(ranges.iter().map(|&(start, end)| {
+                let build_client = &build_client;
+                let remaining = &remaining;
+                async move {
+                    let response = download_request(&build_client()?, request_url, options)
+                        .header(reqwest::header::RANGE, format!("bytes={}-{}", start, end))
+                        .timeout(remaining())
+                        .send()
+                        .await
+                        .map_err(|e| anyhow::anyhow!("Segment request failed: {}", e))?;
+                    // A full body or a different range would double count bytes across segments
+                    if response.status() != reqwest::StatusCode::PARTIAL_CONTENT {
+                        return Err(anyhow::anyhow!(
+                            "Segment {}-{} returned HTTP {} instead of 206",
+                            start,
+                            end,
+                            response.status()
+                        ));
+                    }
+                    match content_range_bounds(&response) {
+                        Some((first, last)) if first == start && last <= end => Ok(response),
+                        _ => Err(anyhow::anyhow!("Segment {}-{} returned a mismatched Content-Range", start, end)),
+                    }
+                }
+            }))
+            .await?;
+            let connections = responses.len();
+            (responses, connections)
+        }
+        // A 206 without a usable Content-Range only carried the probe byte, so request the whole body
+        None if first_response.status() == reqwest::StatusCode::PARTIAL_CONTENT => {
+            println!("Server lacks usable range support, falling back to a single stream");
+            let response = download_request(&client, request_url, options).timeout(remaining()).send().await
+                .map_err(|e| anyhow::anyhow!("HTTP request failed: {}", e))?;
+            (vec![response], 1)
+        }
+        None => (vec![first_response], 1),
+    };
+
+    // Stop at whichever of the time or size limit is reached first
+    futures::future::try_join_all(responses.into_iter().map(|response| consume_response(response, &meter)))
+        .await?;
+    let elapsed_seconds = request_start.elapsed().as_secs_f64();
+    let transfer_duration_seconds = transfer_start.elapsed().as_secs_f64();
+
+    // Probed after the transfer so it doesn't eat into the measured time
+    let quic_reachable = if options.protocol == HttpProtocol::Http3 {
+        let probe = probe_quic(SocketAddr::new(ip, h3_port.unwrap_or(443)), QUIC_PROBE_TIMEOUT).await;
+        Some(probe.reachable && probe.supports_http3_transport)
+    } else {
+        None
+    };
+
+    Ok(AddressMeasurement {
+        connect_time_ms,
+        tls_handshake_time_ms,
+        time_to_first_byte_ms,
+        downloaded_bytes: meter.downloaded_bytes(),
+        elapsed_seconds,
+        transfer_duration_seconds,
+        stop_reason: meter.stop_reason().unwrap_or(DownloadStopReason::Completed),
+        connections,
+        throttling: meter.throttling(),
+        http_version,
+        http3_advertised,
+        quic_reachable,
+        cache_busting,
+    })
+}
+
+// Pick which resolved addresses to measure; with a limit, keep the ones that connect fastest
+pub async fn select_addresses(
+    resolved: &ResolvedHost,
+    all_addresses: bool,
+    max_addresses: Option<usize>,
+    port: u16,
+) -> Vec<IpAddr> {
+    if !all_addresses {
+        return vec![resolved.ip];
+    }
+
+    let limit = match max_addresses {
+        Some(limit) if limit > 0 && limit < resolved.addresses.len() => limit,
+        _ => return resolved.addresses.clone(),
+    };
+
+    let mut ranked: Vec<(IpAddr, Duration)> = futures::future::join_all(resolved.addresses.iter().map(|&ip| async move {
+        let connect_start = Instant::now();
+        let connected = tokio::time::timeout(ADDRESS_RANKING_TIMEOUT, TcpStream::connect(SocketAddr::new(ip, port))).await;
+        let rtt = match connected {
+            Ok(Ok(_)) => connect_start.elapsed(),
+            _ => Duration::MAX,
+        };
+        (ip, rtt)
+    }))
+    .await;
+
+    ranked.sort_by_key(|&(_, rtt)| rtt);
+    ranked.into_iter().take(limit).map(|(ip, _)| ip).collect()
+}
+
+async fn download_with_custom_dns(
+    url: &str,
+    dns_ip: &str,
+    timeout_seconds: u64,
+    options: &DownloadTestOptions,
+    session_id: u64,
+    on_progress: Option<&ProgressCallback>,
+    resolved: Option<ResolvedHost>,
+) -> anyhow::Result<DownloadSpeedResult> {
+    println!(
+        "Starting download test: {} with DNS: {} (max_bytes: {:?}, connections: {:?}, protocol: {:?})",
+        url, dns_ip, options.max_bytes, options.connections, options.protocol
+    );
+    
+    let timeout_duration = std::time::Duration::from_secs(timeout_seconds);
+    
+    let parsed_url = reqwest::Url::parse(url)?;
+    let host = parsed_url.host_str().ok_or_else(|| anyhow::anyhow!("Invalid host"))?;
+    
+    println!("Parsed URL - host: {}, scheme: {}", host, parsed_url.scheme());
+
+    // Reuse an address resolved earlier (e.g. when deduplicating by IP), otherwise resolve now
+    let resolved = match resolved {
+        Some(resolved) => resolved,
+        None => resolve_download_host(url, dns_ip, timeout_seconds, options.test_all_addresses).await?,
+    };
+
+    // The time limit starts after resolution, as it does for addresses resolved up front
+    let overall_start = Instant::now();
+
     // Determine port based on scheme
     let port = match parsed_url.scheme() {
         "https" => 443,
         "http" => 80,
         _ => return Err(anyhow::anyhow!("Unsupported scheme")),
     };
+    if options.protocol == HttpProtocol::Http2 && port == 80 {
+        return Err(anyhow::anyhow!("HTTP/2 requires an https URL"));
+    }
 
-    let socket_addr = SocketAddr::new(resolved_ip, port);
+    let request_url = match options.cache_busting {
+        CacheBusting::QueryParam => cache_busted_url(&parsed_url),
+        CacheBusting::None | CacheBusting::RandomRange => url.to_string(),
+    };
 
-    // Calculate remaining time for HTTP operations
-    let remaining_time = timeout_duration.saturating_sub(overall_start.elapsed());
-    if remaining_time.is_zero() {
-        return Err(anyhow::anyhow!("Operation timed out before HTTP request"));
-    }
+    let context = DownloadContext {
+        url,
+        request_url: &request_url,
+        host,
+        port,
+        dns_ip,
+        options,
+        session_id,
+        on_progress,
+    };
 
-    let client = Client::builder()
-        .danger_accept_invalid_certs(true)
-        .timeout(remaining_time) // Use remaining time, not extra time
-        .resolve(host, socket_addr)
-        .build()?;
+    let addresses = select_addresses(&resolved, options.test_all_addresses, options.max_addresses, port).await;
 
-    let download_start = Instant::now();
-    
-    let response = client.get(url).send().await
-        .map_err(|e| anyhow::anyhow!("HTTP request failed: {}", e))?;
+    // Addresses share the time limit; each gets an even split of whatever is left when it starts
+    let deadline = overall_start + timeout_duration;
+    let mut measurements: Vec<(IpAddr, anyhow::Result<AddressMeasurement>)> = Vec::new();
+    for (index, &ip) in addresses.iter().enumerate() {
+        let remaining_addresses = (addresses.len() - index) as u32;
+        let time_limit = deadline.saturating_duration_since(Instant::now()) / remaining_addresses;
+        println!("Measuring address {} ({}/{}) for up to {:?}", ip, index + 1, addresses.len(), time_limit);
+        measurements.push((ip, measure_address(&context, ip, time_limit).await));
+    }
 
-    let mut downloaded_bytes = 0u64;
-    let mut stream = response.bytes_stream();
+    let address_results: Vec<AddressSpeedResult> = measurements
+        .iter()
+        .map(|(ip, measurement)| match measurement {
+            Ok(m) => AddressSpeedResult {
+                address: ip.to_string(),
+                ip_version: IpVersion::of(ip),
+                success: true,
+                download_speed_mbps: m.speed_mbps(),
+                transfer_speed_mbps: m.transfer_speed_mbps(),
+                downloaded_bytes: m.downloaded_bytes,
+                connect_time_ms: m.connect_time_ms,
+                time_to_first_byte_ms: Some(m.time_to_first_byte_ms),
+                error_message: None,
+            },
+            Err(e) => AddressSpeedResult {
+                address: ip.to_string(),
+                ip_version: IpVersion::of(ip),
+                success: false,
+                download_speed_mbps: 0.0,
+                transfer_speed_mbps: 0.0,
+                downloaded_bytes: 0,
+                connect_time_ms: None,
+                time_to_first_byte_ms: None,
+                error_message: Some(e.to_string()),
+            },
+        })
+        .collect();
 
-    while let Some(chunk_result) = stream.next().await {
-        // Check if overall timeout has been reached
-        if overall_start.elapsed() >= timeout_duration {
-            break;
-        }
+    let best_speed_for = |version: IpVersion| {
+        address_results
+            .iter()
+            .filter(|result| result.success && result.ip_version == version)
+            .map(|result| result.download_speed_mbps)
+            .reduce(f64::max)
+    };
+    let best_ipv4_speed_mbps = best_speed_for(IpVersion::V4);
+    let best_ipv6_speed_mbps = best_speed_for(IpVersion::V6);
 
-        let chunk = chunk_result
-            .map_err(|e| anyhow::anyhow!("Stream error: {}", e))?;
-        downloaded_bytes += chunk.len() as u64;
-        
-        // Add periodic check for cancellation (every 1MB or every 1 second)
-        if downloaded_bytes % (1024 * 1024) == 0 || download_start.elapsed().as_secs() > 1 {
-            tokio::task::yield_now().await; // Allow other tasks to run and check for cancellation
+    // Headline numbers come from the fastest address
+    let mut best: Option<(IpAddr, &AddressMeasurement)> = None;
+    let mut first_error: Option<&anyhow::Error> = None;
+    for (ip, measurement) in &measurements {
+        match measurement {
+            Ok(m) => {
+                if !best.is_some_and(|(_, b)| b.speed_mbps() >= m.speed_mbps()) {
+                    best = Some((*ip, m));
+                }
+            }
+            Err(e) => {
+                first_error.get_or_insert(e);
+            }
         }
     }
 
-    let elapsed = overall_start.elapsed().as_secs_f64(); // Use overall elapsed time
-    let speed_mbps = (downloaded_bytes as f64 * 8.0) / (elapsed * 1_000_000.0);
+    let Some((best_ip, best)) = best else {
+        return Err(match first_error {
+            Some(e) => anyhow::anyhow!("{}", e),
+            None => anyhow::anyhow!("No addresses to test"),
+        });
+    };
 
     Ok(DownloadSpeedResult {
         dns_server: dns_ip.to_string(),
         url: url.to_string(),
         success: true,
-        download_speed_mbps: speed_mbps,
-        downloaded_bytes,
-        test_duration_seconds: elapsed,
+        download_speed_mbps: best.speed_mbps(),
+        downloaded_bytes: best.downloaded_bytes,
+        test_duration_seconds: best.elapsed_seconds,
         error_message: None,
-        resolution_time_ms: Some(resolution_time_ms),
+        resolution_time_ms: Some(resolved.resolution_time_ms),
+        connect_time_ms: best.connect_time_ms,
+        tls_handshake_time_ms: best.tls_handshake_time_ms,
+        time_to_first_byte_ms: Some(best.time_to_first_byte_ms),
+        transfer_speed_mbps: best.transfer_speed_mbps(),
+        transfer_duration_seconds: best.transfer_duration_seconds,
+        resolved_ip: Some(best_ip.to_string()),
+        measured_via_dns_server: None,
+        max_bytes: options.max_bytes,
+        stop_reason: Some(best.stop_reason),
+        connections: best.connections,
+        address_results,
+        best_ipv4_speed_mbps,
+        best_ipv6_speed_mbps,
+        throttled_after_bytes: best.throttling.map(|t| t.throttled_after_bytes),
+        throttled_after_seconds: best.throttling.map(|t| t.throttled_after_seconds),
+        pre_throttle_speed_mbps: best.throttling.map(|t| t.pre_throttle_speed_mbps),
+        post_throttle_speed_mbps: best.throttling.map(|t| t.post_throttle_speed_mbps),
+        http_version: Some(best.http_version.clone()),
+        http3_advertised: Some(best.http3_advertised),
+        quic_reachable: best.quic_reachable,
+        protocol_note: (options.protocol == HttpProtocol::Http3).then(|| {
+            format!("HTTP/3 transfers aren't supported; QUIC was only probed and the download used {}", best.http_version)
+        }),
+        randomized_order: options.randomize_order,
+        warm_up: options.warm_up,
+        cache_busting: best.cache_busting,
+        test_position: None,
         session_id: 0, // This will be set by the calling function
     })
 }
 
-pub async fn test_download_speed_with_dns(url: String, dns_server: String, timeout_seconds: u64, session_id: u64) -> DownloadSpeedResult {
+pub fn failed_download_result(dns_server: String, url: String, error: String, session_id: u64) -> DownloadSpeedResult {
+    DownloadSpeedResult {
+        dns_server,
+        url,
+        success: false,
+        download_speed_mbps: 0.0,
+        downloaded_bytes: 0,
+        test_duration_seconds: 0.0,
+        error_message: Some(error),
+        resolution_time_ms: None,
+        connect_time_ms: None,
+        tls_handshake_time_ms: None,
+        time_to_first_byte_ms: None,
+        transfer_speed_mbps: 0.0,
+        transfer_duration_seconds: 0.0,
+        resolved_ip: None,
+        measured_via_dns_server: None,
+        max_bytes: None,
+        stop_reason: None,
+        connections: 0,
+        address_results: Vec::new(),
+        best_ipv4_speed_mbps: None,
+        best_ipv6_speed_mbps: None,
+        throttled_after_bytes: None,
+        throttled_after_seconds: None,
+        pre_throttle_speed_mbps: None,
+        post_throttle_speed_mbps: None,
+        http_version: None,
+        http3_advertised: None,
+        quic_reachable: None,
+        protocol_note: None,
+        randomized_order: false,
+        warm_up: false,
+        cache_busting: CacheBusting::None,
+        test_position: None,
+        session_id,
+    }
+}
+
+pub async fn test_download_speed_with_dns(
+    url: String,
+    dns_server: String,
+    timeout_seconds: u64,
+    options: &DownloadTestOptions,
+    session_id: u64,
+    on_progress: Option<ProgressCallback>,
+    resolved: Option<ResolvedHost>,
+) -> DownloadSpeedResult {
     // Add a small delay to allow for cancellation check
     tokio::time::sleep(std::time::Duration::from_millis(10)).await;
-    
-    match download_with_custom_dns(&url, &dns_server, timeout_seconds, session_id).await {
+
+    // Skipped tests still report a result so the run shows why they didn't happen
+    let outcome = if data_budget_exceeded() {
+        Err(anyhow::anyhow!(DATA_BUDGET_EXCEEDED))
+    } else {
+        download_with_custom_dns(
+            &url,
+            &dns_server,
+            timeout_seconds,
+            options,
+            session_id,
+            on_progress.as_ref(),
+            resolved,
+        )
+        .await
+    };
+
+    match outcome {
         Ok(mut result) => {
             result.session_id = session_id;
             result
         },
-        Err(e) => DownloadSpeedResult {
-            dns_server,
-            url,
-            success: false,
-            download_speed_mbps: 0.0,
-            downloaded_bytes: 0,
-            test_duration_seconds: 0.0,
-            error_message: Some(e.to_string()),
-            resolution_time_ms: None,
-            session_id,
-        },
+        Err(e) => {
+            let mut result = failed_download_result(dns_server, url, e.to_string(), session_id);
+            result.randomized_order = options.randomize_order;
+            result.warm_up = options.warm_up;
+            result.cache_busting = options.cache_busting;
+            result
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn content_range_bounds_accepts_byte_ranges() {
+        let cases = [
+            ("bytes 0-0/12345", Some((0, 0))),
+            ("bytes 100-199/12345", Some((100, 199))),
+            ("bytes 100-199/*", Some((100, 199))),
+            ("bytes  5 - 9 /10", Some((5, 9))),
+        ];
+        for (value, expected) in cases {
+            assert_eq!(parse_content_range_bounds(value), expected, "{:?}", value);
+        }
+    }
+
+    #[test]
+    fn content_range_bounds_rejects_malformed_values() {
+        for value in ["", "bytes */12345", "bytes 100/12345", "bytes a-b/10", "items 0-9/10", "0-9/10", "bytes 9-5/10", "bytes -1-5/10"] {
+            assert_eq!(parse_content_range_bounds(value), None, "{:?}", value);
+        }
+    }
+
+    #[test]
+    fn alt_svc_h3_port_with_and_without_host() {
+        let cases = [
+            (r#"h3=":443"; ma=86400"#, Some(443)),
+            (r#"h3="alt.example.com:8443"; ma=3600"#, Some(8443)),
+            (r#"h3="[2001:db8::1]:4433""#, Some(4433)),
+            (r#"h2=":443", h3=":4443"; ma=60"#, Some(4443)),
+            (r#"h3-29=":443", h3=":8443""#, Some(443)),
+            (r#"h2=":443""#, None),
+            (r#"h3="alt.example.com""#, None),
+            (r#"h3=":notaport""#, None),
+            ("clear", None),
+        ];
+        for (value, expected) in cases {
+            assert_eq!(parse_alt_svc_h3_port(value), expected, "{:?}", value);
+        }
+    }
+
+    #[test]
+    fn split_ranges_covers_every_byte_once() {
+        let check = |total: u64, connections: usize, expected: &[(u64, u64)]| {
+            assert_eq!(split_ranges(total, connections), expected, "total={} connections={}", total, connections);
+        };
+        check(10, 1, &[(0, 9)]);
+        check(10, 3, &[(0, 3), (4, 7), (8, 9)]);
+        check(10, 5, &[(0, 1), (2, 3), (4, 5), (6, 7), (8, 9)]);
+        // More connections than bytes leaves one byte per segment
+        check(2, 4, &[(0, 0), (1, 1)]);
+        check(0, 4, &[]);
+    }
+
+    #[test]
+    fn split_ranges_uneven_total_stays_contiguous() {
+        let ranges = split_ranges(1_000_003, 7);
+        assert!(ranges.len() <= 7);
+        assert_eq!(ranges.first().map(|r| r.0), Some(0));
+        assert_eq!(ranges.last().map(|r| r.1), Some(1_000_002));
+        assert!(ranges.windows(2).all(|pair| pair[1].0 == pair[0].1 + 1));
     }
 }
@@ -0,0 +1,174 @@
+use std::net::SocketAddr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::net::UdpSocket;
+
+// Reserved "greasing" version from RFC 9000 section 15 that no server implements,
+// so any QUIC server must answer with a Version Negotiation packet
+const GREASE_VERSION: u32 = 0x0a1a_2a3a;
+const QUIC_V1: u32 = 0x0000_0001;
+const QUIC_V2: u32 = 0x6b33_43cf;
+// Servers only answer datagrams of at least this size (RFC 9000 section 14.1)
+const MIN_INITIAL_SIZE: usize = 1200;
+
+#[derive(Debug, Clone, Copy)]
+pub struct QuicProbe {
+    pub reachable: bool,
+    // Server listed QUIC v1 or v2, which HTTP/3 runs over
+    pub supports_http3_transport: bool,
+}
+
+fn connection_id() -> [u8; 8] {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or_default();
+    nanos.to_be_bytes()
+}
+
+fn build_probe_packet() -> Vec<u8> {
+    let mut packet = Vec::with_capacity(MIN_INITIAL_SIZE);
+    packet.push(0xc0); // Long header with the fixed bit set
+    packet.extend_from_slice(&GREASE_VERSION.to_be_bytes());
+    let dcid = connection_id();
+    packet.push(dcid.len() as u8);
+    packet.extend_from_slice(&dcid);
+    packet.push(0); // Empty source connection ID
+    packet.resize(MIN_INITIAL_SIZE, 0);
+    packet
+}
+
+// Supported versions from a Version Negotiation packet, or None if it isn't one
+fn parse_version_negotiation(packet: &[u8]) -> Option<Vec<u32>> {
+    if packet.len() < 7 || packet[0] & 0x80 == 0 || packet[1..5] != [0, 0, 0, 0] {
+        return None;
+    }
+
+    let dcid_len = *packet.get(5)? as usize;
+    let scid_len_pos = 6 + dcid_len;
+    let scid_len = *packet.get(scid_len_pos)? as usize;
+    let versions = packet.get(scid_len_pos + 1 + scid_len..)?;
+
+    Some(
+        versions
+            .chunks_exact(4)
+            .map(|chunk| u32::from_be_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+            .collect(),
+    )
+}
+
+// Check whether UDP/443 reaches a QUIC server by provoking a Version Negotiation reply,
+// which needs no handshake crypto and works even though the HTTP client itself lacks HTTP/3
+pub async fn probe_quic(addr: SocketAddr, timeout: Duration) -> QuicProbe {
+    let unreachable = QuicProbe { reachable: false, supports_http3_transport: false };
+
+    let bind_addr: SocketAddr = if addr.is_ipv4() {
+        ([0, 0, 0, 0], 0).into()
+    } else {
+        ([0u16; 8], 0).into()
+    };
+    let socket = match UdpSocket::bind(bind_addr).await {
+        Ok(socket) => socket,
+        Err(e) => {
+            println!("Failed to bind UDP socket for QUIC probe: {}", e);
+            return unreachable;
+        }
+    };
+
+    if let Err(e) = socket.send_to(&build_probe_packet(), addr).await {
+        println!("Failed to send QUIC probe to {}: {}", addr, e);
+        return unreachable;
+    }
+
+    let mut buffer = [0u8; 1500];
+    match tokio::time::timeout(timeout, socket.recv_from(&mut buffer)).await {
+        Ok(Ok((len, from))) if from == addr => match parse_version_negotiation(&buffer[..len]) {
+            Some(versions) => {
+                println!("QUIC version negotiation from {}: {:08x?}", addr, versions);
+                QuicProbe {
+                    reachable: true,
+                    supports_http3_transport: versions.iter().any(|&v| v == QUIC_V1 || v == QUIC_V2),
+                }
+            }
+            None => QuicProbe { reachable: true, supports_http3_transport: false },
+        },
+        Ok(Ok(_)) => unreachable,
+        Ok(Err(e)) => {
+            println!("QUIC probe to {} failed: {}", addr, e);
+            unreachable
+        }
+        Err(_) => {
+            println!("QUIC probe to {} timed out", addr);
+            unreachable
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Version Negotiation packet: long header, version 0, connection IDs, then the supported versions
+    fn version_negotiation(dcid: &[u8], scid: &[u8], versions: &[u8]) -> Vec<u8> {
+        let mut packet = vec![0x80, 0, 0, 0, 0, dcid.len() as u8];
+        packet.extend_from_slice(dcid);
+        packet.push(scid.len() as u8);
+        packet.extend_from_slice(scid);
+        packet.extend_from_slice(versions);
+        packet
+    }
+
+    #[test]
+    fn parses_supported_versions() {
+        let mut versions = QUIC_V1.to_be_bytes().to_vec();
+        versions.extend_from_slice(&QUIC_V2.to_be_bytes());
+        let packet = version_negotiation(&[1, 2, 3, 4, 5, 6, 7, 8], &[9, 9], &versions);
+
+        assert_eq!(parse_version_negotiation(&packet), Some(vec![QUIC_V1, QUIC_V2]));
+    }
+
+    #[test]
+    fn trailing_partial_version_is_ignored() {
+        let mut versions = QUIC_V1.to_be_bytes().to_vec();
+        versions.extend_from_slice(&[0xff, 0x00]);
+        let packet = version_negotiation(&[], &[], &versions);
+
+        assert_eq!(parse_version_negotiation(&packet), Some(vec![QUIC_V1]));
+    }
+
+    #[test]
+    fn rejects_malformed_packets() {
+        let valid = version_negotiation(&[1, 2, 3, 4], &[5], &QUIC_V1.to_be_bytes());
+
+        let mut short_header = valid.clone();
+        short_header[0] = 0x40;
+        let mut not_negotiation = valid.clone();
+        not_negotiation[1..5].copy_from_slice(&QUIC_V1.to_be_bytes());
+        // Destination connection ID length runs past the end of the packet
+        let mut truncated_dcid = valid.clone();
+        truncated_dcid[5] = 200;
+        // Source connection ID length runs past the end of the packet
+        let truncated_scid = [0x80, 0, 0, 0, 0, 4, 1, 2, 3, 4, 50];
+
+        let cases: [(&str, &[u8]); 6] = [
+            ("empty", &[]),
+            ("too short", &valid[..6]),
+            ("short header", &short_header),
+            ("non-zero version", &not_negotiation),
+            ("dcid overflow", &truncated_dcid),
+            ("scid overflow", &truncated_scid),
+        ];
+        for (name, packet) in cases {
+            assert_eq!(parse_version_negotiation(packet), None, "{}", name);
+        }
+    }
+
+    #[test]
+    fn probe_packet_is_a_padded_grease_initial() {
+        let packet = build_probe_packet();
+
+        assert_eq!(packet.len(), MIN_INITIAL_SIZE);
+        assert_eq!(packet[0] & 0xc0, 0xc0);
+        assert_eq!(packet[1..5], GREASE_VERSION.to_be_bytes());
+        assert_eq!(parse_version_negotiation(&packet), None);
+    }
+}
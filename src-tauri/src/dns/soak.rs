@@ -0,0 +1,180 @@
+use serde::{Deserialize, Serialize};
+
+// Latency drift compares the first and last quarter of successful samples
+const DRIFT_WINDOW_DIVISOR: usize = 4;
+
+// One probe of the time series streamed during a stability test
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SoakSample {
+    pub dns_server: String,
+    pub domain: String,
+    // 0-based probe round
+    pub round: u64,
+    // Seconds since the stability test started
+    pub elapsed_seconds: f64,
+    pub success: bool,
+    pub response_time_ms: Option<u64>,
+    pub error_message: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SoakSummary {
+    pub dns_server: String,
+    pub domain: String,
+    pub samples: usize,
+    pub successful_samples: usize,
+    pub availability_percent: f64,
+    pub outages: usize,
+    pub longest_outage_seconds: f64,
+    pub min_response_time_ms: Option<u64>,
+    pub avg_response_time_ms: Option<f64>,
+    pub max_response_time_ms: Option<u64>,
+    // Average latency of the last quarter minus the first; positive means the server got slower
+    pub latency_drift_ms: Option<f64>,
+}
+
+fn average(values: &[u64]) -> Option<f64> {
+    (!values.is_empty()).then(|| values.iter().sum::<u64>() as f64 / values.len() as f64)
+}
+
+// An outage runs from its first failed sample until the next success, or the end of the test
+pub fn summarize_soak(
+    dns_server: &str,
+    domain: &str,
+    samples: &[SoakSample],
+    ended_at_seconds: f64,
+) -> SoakSummary {
+    let mut outages = 0;
+    let mut longest_outage_seconds: f64 = 0.0;
+    let mut outage_start: Option<f64> = None;
+
+    for sample in samples {
+        match (sample.success, outage_start) {
+            (false, None) => {
+                outages += 1;
+                outage_start = Some(sample.elapsed_seconds);
+            }
+            (true, Some(start)) => {
+                longest_outage_seconds = longest_outage_seconds.max(sample.elapsed_seconds - start);
+                outage_start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(start) = outage_start {
+        longest_outage_seconds = longest_outage_seconds.max(ended_at_seconds - start);
+    }
+
+    let latencies: Vec<u64> = samples
+        .iter()
+        .filter(|sample| sample.success)
+        .filter_map(|sample| sample.response_time_ms)
+        .collect();
+    let successful_samples = samples.iter().filter(|sample| sample.success).count();
+
+    let window = latencies.len() / DRIFT_WINDOW_DIVISOR;
+    let latency_drift_ms = if window > 0 {
+        let first = average(&latencies[..window]);
+        let last = average(&latencies[latencies.len() - window..]);
+        first.zip(last).map(|(first, last)| last - first)
+    } else {
+        None
+    };
+
+    SoakSummary {
+        dns_server: dns_server.to_string(),
+        domain: domain.to_string(),
+        samples: samples.len(),
+        successful_samples,
+        availability_percent: if samples.is_empty() {
+            0.0
+        } else {
+            successful_samples as f64 * 100.0 / samples.len() as f64
+        },
+        outages,
+        longest_outage_seconds,
+        min_response_time_ms: latencies.iter().copied().min(),
+        avg_response_time_ms: average(&latencies),
+        max_response_time_ms: latencies.iter().copied().max(),
+        latency_drift_ms,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // One sample per second; `None` marks a failed probe
+    fn samples(response_times: &[Option<u64>]) -> Vec<SoakSample> {
+        response_times
+            .iter()
+            .enumerate()
+            .map(|(round, response_time_ms)| SoakSample {
+                dns_server: "1.1.1.1".to_string(),
+                domain: "example.com".to_string(),
+                round: round as u64,
+                elapsed_seconds: round as f64,
+                success: response_time_ms.is_some(),
+                response_time_ms: *response_time_ms,
+                error_message: response_time_ms.is_none().then(|| "timed out".to_string()),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn counts_outages_and_longest_gap() {
+        let summary = summarize_soak(
+            "1.1.1.1",
+            "example.com",
+            &samples(&[Some(10), None, None, Some(10), None, Some(10), Some(10), Some(10)]),
+            8.0,
+        );
+
+        assert_eq!(summary.samples, 8);
+        assert_eq!(summary.successful_samples, 5);
+        assert!((summary.availability_percent - 62.5).abs() < 1e-9);
+        assert_eq!(summary.outages, 2);
+        assert!((summary.longest_outage_seconds - 2.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn outage_still_open_runs_to_the_end_of_the_test() {
+        let summary = summarize_soak("1.1.1.1", "example.com", &samples(&[Some(10), Some(10), None, None]), 10.0);
+
+        assert_eq!(summary.outages, 1);
+        assert!((summary.longest_outage_seconds - 8.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn latency_stats_and_drift_use_successful_samples_only() {
+        let summary = summarize_soak(
+            "1.1.1.1",
+            "example.com",
+            &samples(&[Some(10), Some(20), None, Some(30), Some(40), Some(50), Some(60), Some(70), Some(80)]),
+            9.0,
+        );
+
+        assert_eq!(summary.min_response_time_ms, Some(10));
+        assert_eq!(summary.max_response_time_ms, Some(80));
+        assert_eq!(summary.avg_response_time_ms, Some(45.0));
+        // First quarter averages 15ms and last quarter 75ms
+        assert_eq!(summary.latency_drift_ms, Some(60.0));
+    }
+
+    #[test]
+    fn drift_needs_at_least_four_successful_samples() {
+        let summary = summarize_soak("1.1.1.1", "example.com", &samples(&[Some(10), Some(20), Some(30)]), 3.0);
+        assert_eq!(summary.latency_drift_ms, None);
+    }
+
+    #[test]
+    fn empty_test_has_no_availability() {
+        let summary = summarize_soak("1.1.1.1", "example.com", &[], 0.0);
+
+        assert_eq!(summary.samples, 0);
+        assert_eq!(summary.availability_percent, 0.0);
+        assert_eq!(summary.outages, 0);
+        assert_eq!(summary.min_response_time_ms, None);
+        assert_eq!(summary.avg_response_time_ms, None);
+    }
+}
@@ -0,0 +1,90 @@
+// Minimal blocking HTTP/1.1 server so the speed tests can run against a local backend
+use std::io::{BufRead, BufReader, Read};
+use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
+
+pub struct ReceivedRequest {
+    pub method: String,
+    // Path without the query string
+    pub path: String,
+    pub query: String,
+    pub content_length: Option<u64>,
+    pub chunked: bool,
+    pub body_bytes: u64,
+}
+
+// Read one request and drain its body; None once the client closes the connection
+pub fn read_request(reader: &mut BufReader<TcpStream>) -> Option<ReceivedRequest> {
+    let mut request_line = String::new();
+    if reader.read_line(&mut request_line).ok()? == 0 {
+        return None;
+    }
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next()?.to_string();
+    let target = parts.next()?;
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let (path, query) = (path.to_string(), query.to_string());
+
+    let mut content_length = None;
+    let mut chunked = false;
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).ok()?;
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            match name.to_ascii_lowercase().as_str() {
+                "content-length" => content_length = value.trim().parse().ok(),
+                "transfer-encoding" => chunked = value.trim().eq_ignore_ascii_case("chunked"),
+                _ => {}
+            }
+        }
+    }
+
+    let mut body_bytes = 0u64;
+    if chunked {
+        loop {
+            let mut size_line = String::new();
+            reader.read_line(&mut size_line).ok()?;
+            let size = u64::from_str_radix(size_line.trim().split(';').next()?, 16).ok()?;
+            // Chunk data plus its trailing CRLF
+            let mut data = vec![0u8; size as usize + 2];
+            reader.read_exact(&mut data).ok()?;
+            body_bytes += size;
+            if size == 0 {
+                break;
+            }
+        }
+    } else if let Some(length) = content_length {
+        body_bytes = std::io::copy(&mut reader.by_ref().take(length), &mut std::io::sink()).ok()?;
+    }
+
+    Some(ReceivedRequest { method, path, query, content_length, chunked, body_bytes })
+}
+
+// Serve keep-alive connections, each on its own thread, until the test process exits
+pub fn serve<F>(respond: F) -> u16
+where
+    F: Fn(&ReceivedRequest, &mut TcpStream) -> std::io::Result<()> + Send + Sync + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let respond = Arc::new(respond);
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else { continue };
+            let respond = respond.clone();
+            std::thread::spawn(move || {
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                while let Some(request) = read_request(&mut reader) {
+                    if respond(&request, &mut stream).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+    });
+    port
+}
@@ -0,0 +1,126 @@
+use std::time::Duration;
+
+use super::mbps;
+
+// Width of the buckets the transfer is split into for throttling analysis
+pub const THROUGHPUT_SAMPLE_INTERVAL: Duration = Duration::from_millis(500);
+
+// Post-drop speed must be at most this fraction of the pre-drop speed
+const THROTTLE_SPEED_RATIO: f64 = 0.5;
+// No single bucket after the drop may recover above this fraction of the pre-drop speed
+const THROTTLE_RECOVERY_RATIO: f64 = 0.75;
+const MIN_PRE_THROTTLE_SECONDS: f64 = 1.0;
+const MIN_POST_THROTTLE_SECONDS: f64 = 2.0;
+
+#[derive(Debug, Clone, Copy)]
+pub struct ThroughputSample {
+    pub duration_seconds: f64,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ThrottleDetection {
+    pub throttled_after_bytes: u64,
+    pub throttled_after_seconds: f64,
+    pub pre_throttle_speed_mbps: f64,
+    pub post_throttle_speed_mbps: f64,
+}
+
+// Look for the split point with the sharpest sustained drop in throughput (burst, then throttle)
+pub fn detect_throttling(samples: &[ThroughputSample]) -> Option<ThrottleDetection> {
+    let total_bytes: u64 = samples.iter().map(|s| s.bytes).sum();
+    let total_seconds: f64 = samples.iter().map(|s| s.duration_seconds).sum();
+
+    let mut best: Option<(f64, ThrottleDetection)> = None;
+    let mut pre_bytes = 0u64;
+    let mut pre_seconds = 0.0;
+
+    for (split, sample) in samples.iter().enumerate().take(samples.len().saturating_sub(1)) {
+        pre_bytes += sample.bytes;
+        pre_seconds += sample.duration_seconds;
+
+        let post_bytes = total_bytes - pre_bytes;
+        let post_seconds = total_seconds - pre_seconds;
+        if pre_seconds < MIN_PRE_THROTTLE_SECONDS || post_seconds < MIN_POST_THROTTLE_SECONDS {
+            continue;
+        }
+
+        let pre_speed = mbps(pre_bytes, pre_seconds);
+        let post_speed = mbps(post_bytes, post_seconds);
+        if pre_speed <= 0.0 || post_speed > pre_speed * THROTTLE_SPEED_RATIO {
+            continue;
+        }
+
+        let recovered = samples[split + 1..]
+            .iter()
+            .any(|s| mbps(s.bytes, s.duration_seconds) > pre_speed * THROTTLE_RECOVERY_RATIO);
+        if recovered {
+            continue;
+        }
+
+        let drop_ratio = post_speed / pre_speed;
+        if !best.is_some_and(|(best_ratio, _)| best_ratio <= drop_ratio) {
+            best = Some((
+                drop_ratio,
+                ThrottleDetection {
+                    throttled_after_bytes: pre_bytes,
+                    throttled_after_seconds: pre_seconds,
+                    pre_throttle_speed_mbps: pre_speed,
+                    post_throttle_speed_mbps: post_speed,
+                },
+            ));
+        }
+    }
+
+    best.map(|(_, detection)| detection)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn samples(count: usize, bytes: u64) -> Vec<ThroughputSample> {
+        (0..count)
+            .map(|_| ThroughputSample { duration_seconds: 0.5, bytes })
+            .collect()
+    }
+
+    #[test]
+    fn detects_sustained_drop_after_burst() {
+        let mut transfer = samples(6, 5_000_000);
+        transfer.extend(samples(10, 500_000));
+
+        let detection = detect_throttling(&transfer).expect("throttling should be detected");
+        assert_eq!(detection.throttled_after_bytes, 30_000_000);
+        assert!((detection.throttled_after_seconds - 3.0).abs() < 1e-9);
+        assert!((detection.pre_throttle_speed_mbps - 80.0).abs() < 1e-9);
+        assert!((detection.post_throttle_speed_mbps - 8.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn steady_transfer_is_not_throttled() {
+        assert!(detect_throttling(&samples(16, 5_000_000)).is_none());
+    }
+
+    #[test]
+    fn recovery_after_drop_is_not_throttled() {
+        let mut transfer = samples(6, 5_000_000);
+        transfer.extend(samples(5, 500_000));
+        transfer.extend(samples(1, 5_000_000));
+
+        assert!(detect_throttling(&transfer).is_none());
+    }
+
+    #[test]
+    fn short_drop_is_ignored() {
+        let mut transfer = samples(6, 5_000_000);
+        transfer.extend(samples(3, 500_000));
+        assert!(detect_throttling(&transfer).is_none());
+    }
+
+    #[test]
+    fn empty_and_single_sample_inputs() {
+        assert!(detect_throttling(&[]).is_none());
+        assert!(detect_throttling(&samples(1, 5_000_000)).is_none());
+    }
+}
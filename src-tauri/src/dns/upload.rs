@@ -0,0 +1,337 @@
+use reqwest::{Body, Client};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::usage::{data_budget_exceeded, record_uploaded_bytes, DATA_BUDGET_EXCEEDED};
+
+use super::{mbps, resolve_download_host, with_headers_and_auth, DownloadAuth, DownloadStopReason, PROGRESS_INTERVAL};
+
+const DEFAULT_UPLOAD_BYTES: u64 = 10 * 1024 * 1024;
+pub(super) const UPLOAD_CHUNK_SIZE: usize = 64 * 1024;
+// Stop sending this long before the time limit so the server still has time to answer
+const UPLOAD_RESPONSE_GRACE: Duration = Duration::from_secs(2);
+
+// Random bytes so compressing proxies can't shrink the payload
+pub(super) fn random_payload() -> Arc<Vec<u8>> {
+    Arc::new(std::iter::repeat_with(|| fastrand::u8(..)).take(UPLOAD_CHUNK_SIZE).collect())
+}
+
+// Counts upload body chunks once they are written rather than when they are handed to the client.
+// The client asks for the next chunk once the previous one is written, so each chunk is counted
+// on the following poll, and the last one by a final `commit` after the response.
+#[derive(Clone)]
+pub(super) struct WrittenBytes {
+    sent: Arc<AtomicU64>,
+    in_flight: Arc<AtomicU64>,
+}
+
+impl WrittenBytes {
+    pub(super) fn new(sent: Arc<AtomicU64>) -> Self {
+        Self { sent, in_flight: Arc::new(AtomicU64::new(0)) }
+    }
+
+    // Count the chunk handed out last and return the total written so far
+    pub(super) fn commit(&self) -> u64 {
+        let written = self.in_flight.swap(0, Ordering::Relaxed);
+        record_uploaded_bytes(written);
+        self.sent.fetch_add(written, Ordering::Relaxed) + written
+    }
+
+    pub(super) fn hand_out(&self, len: usize) {
+        self.in_flight.store(len as u64, Ordering::Relaxed);
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+pub enum UploadMethod {
+    #[default]
+    Post,
+    Put,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct UploadTestOptions {
+    pub method: UploadMethod,
+    // Send exactly this much with a Content-Length; without it a chunked body stops at DEFAULT_UPLOAD_BYTES or the time limit
+    pub size_bytes: Option<u64>,
+    pub headers: HashMap<String, String>,
+    pub auth: Option<DownloadAuth>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct UploadSpeedResult {
+    pub dns_server: String,
+    pub url: String,
+    pub success: bool,
+    pub upload_speed_mbps: f64,
+    pub uploaded_bytes: u64,
+    pub test_duration_seconds: f64,
+    pub error_message: Option<String>,
+    pub resolution_time_ms: Option<u64>,
+    pub resolved_ip: Option<String>,
+    pub status_code: Option<u16>,
+    pub stop_reason: Option<DownloadStopReason>,
+    pub session_id: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct UploadProgress {
+    pub dns_server: String,
+    pub url: String,
+    pub uploaded_bytes: u64,
+    pub average_speed_mbps: f64,
+    pub elapsed_seconds: f64,
+    pub session_id: u64,
+}
+
+pub type UploadProgressCallback = Arc<dyn Fn(UploadProgress) + Send + Sync>;
+
+async fn upload_with_custom_dns(
+    url: &str,
+    dns_ip: &str,
+    timeout_seconds: u64,
+    options: &UploadTestOptions,
+    session_id: u64,
+    on_progress: Option<UploadProgressCallback>,
+) -> anyhow::Result<UploadSpeedResult> {
+    println!(
+        "Starting upload test: {} with DNS: {} (method: {:?}, size_bytes: {:?})",
+        url, dns_ip, options.method, options.size_bytes
+    );
+
+    let parsed_url = reqwest::Url::parse(url)?;
+    let host = parsed_url.host_str().ok_or_else(|| anyhow::anyhow!("Invalid host"))?;
+    let port = parsed_url
+        .port_or_known_default()
+        .ok_or_else(|| anyhow::anyhow!("Unsupported scheme"))?;
+
+    let resolved = resolve_download_host(url, dns_ip, timeout_seconds, false).await?;
+
+    // Resolution is reported separately, so the time limit covers only the upload as for downloads
+    let overall_start = Instant::now();
+    let time_limit = Duration::from_secs(timeout_seconds);
+
+    let client = Client::builder()
+        .danger_accept_invalid_certs(true)
+        .timeout(time_limit)
+        .resolve(host, SocketAddr::new(resolved.ip, port))
+        .build()?;
+
+    // A known size is sent in full; a chunked body stops early so the server still has time to answer
+    let fixed_size = options.size_bytes.filter(|&size| size > 0);
+    let total_bytes = fixed_size.unwrap_or(DEFAULT_UPLOAD_BYTES);
+    let send_limit = time_limit.saturating_sub(UPLOAD_RESPONSE_GRACE);
+    let chunk = random_payload();
+    let sent_bytes = Arc::new(AtomicU64::new(0));
+    let written_bytes = WrittenBytes::new(sent_bytes.clone());
+
+    let upload_start = Instant::now();
+    let body_stream = {
+        let written_bytes = written_bytes.clone();
+        let dns_server = dns_ip.to_string();
+        let url = url.to_string();
+        futures::stream::unfold(Instant::now(), move |last_progress_time| {
+            let chunk = chunk.clone();
+            let written_bytes = written_bytes.clone();
+            let on_progress = on_progress.clone();
+            let dns_server = dns_server.clone();
+            let url = url.clone();
+            async move {
+                let sent = written_bytes.commit();
+
+                if sent >= total_bytes {
+                    return None;
+                }
+                if fixed_size.is_none() && (upload_start.elapsed() >= send_limit || data_budget_exceeded()) {
+                    return None;
+                }
+                if fixed_size.is_some() && data_budget_exceeded() {
+                    // Ending early would break the Content-Length, so fail the request instead
+                    let error = std::io::Error::other(DATA_BUDGET_EXCEEDED);
+                    return Some((Err(error), last_progress_time));
+                }
+
+                let len = (total_bytes - sent).min(UPLOAD_CHUNK_SIZE as u64) as usize;
+                written_bytes.hand_out(len);
+
+                let last_progress_time = match on_progress {
+                    Some(on_progress) if last_progress_time.elapsed() >= PROGRESS_INTERVAL => {
+                        let elapsed = upload_start.elapsed().as_secs_f64();
+                        on_progress(UploadProgress {
+                            dns_server,
+                            url,
+                            uploaded_bytes: sent,
+                            average_speed_mbps: mbps(sent, elapsed),
+                            elapsed_seconds: elapsed,
+                            session_id,
+                        });
+                        Instant::now()
+                    }
+                    _ => last_progress_time,
+                };
+
+                Some((Ok(chunk[..len].to_vec()), last_progress_time))
+            }
+        })
+    };
+
+    let request = match options.method {
+        UploadMethod::Post => client.post(url),
+        UploadMethod::Put => client.put(url),
+    };
+    let mut request = with_headers_and_auth(request, &options.headers, options.auth.as_ref())
+        .header(reqwest::header::CONTENT_TYPE, "application/octet-stream");
+    if let Some(size) = fixed_size {
+        request = request.header(reqwest::header::CONTENT_LENGTH, size);
+    }
+    let response = request
+        .body(Body::wrap_stream(body_stream))
+        .send()
+        .await
+        .map_err(|e| anyhow::anyhow!("HTTP request failed: {}", e))?;
+    // The server answers once it has read the whole body, so this is the upload time
+    let upload_seconds = upload_start.elapsed().as_secs_f64();
+
+    // With a Content-Length the client stops after the last chunk without asking for more
+    written_bytes.commit();
+
+    let status = response.status();
+    if !status.is_success() {
+        return Err(anyhow::anyhow!("HTTP error: {}", status));
+    }
+
+    let uploaded_bytes = sent_bytes.load(Ordering::Relaxed);
+    let upload_speed_mbps = mbps(uploaded_bytes, upload_seconds);
+    println!(
+        "Uploaded {} bytes to {} in {:.2}s ({:.3} Mbps)",
+        uploaded_bytes, url, upload_seconds, upload_speed_mbps
+    );
+
+    Ok(UploadSpeedResult {
+        dns_server: dns_ip.to_string(),
+        url: url.to_string(),
+        success: true,
+        upload_speed_mbps,
+        uploaded_bytes,
+        test_duration_seconds: overall_start.elapsed().as_secs_f64(),
+        error_message: None,
+        resolution_time_ms: Some(resolved.resolution_time_ms),
+        resolved_ip: Some(resolved.ip.to_string()),
+        status_code: Some(status.as_u16()),
+        stop_reason: Some(if uploaded_bytes >= total_bytes {
+            DownloadStopReason::Completed
+        } else if data_budget_exceeded() {
+            DownloadStopReason::DataBudget
+        } else {
+            DownloadStopReason::TimeLimit
+        }),
+        session_id,
+    })
+}
+
+pub async fn test_upload_speed_with_dns(
+    url: String,
+    dns_server: String,
+    timeout_seconds: u64,
+    options: &UploadTestOptions,
+    session_id: u64,
+    on_progress: Option<UploadProgressCallback>,
+) -> UploadSpeedResult {
+    let outcome = if data_budget_exceeded() {
+        Err(anyhow::anyhow!(DATA_BUDGET_EXCEEDED))
+    } else {
+        upload_with_custom_dns(&url, &dns_server, timeout_seconds, options, session_id, on_progress).await
+    };
+
+    match outcome {
+        Ok(result) => result,
+        Err(e) => UploadSpeedResult {
+            dns_server,
+            url,
+            success: false,
+            upload_speed_mbps: 0.0,
+            uploaded_bytes: 0,
+            test_duration_seconds: 0.0,
+            error_message: Some(e.to_string()),
+            resolution_time_ms: None,
+            resolved_ip: None,
+            status_code: None,
+            stop_reason: None,
+            session_id,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns::SYSTEM_DNS;
+    use crate::dns::test_server::read_request;
+    use std::io::{BufReader, Write};
+    use std::net::TcpListener;
+
+    // What a one-request HTTP sink saw: the Content-Length header, if any, and the body bytes read
+    struct SinkReport {
+        content_length: Option<u64>,
+        chunked: bool,
+        received_bytes: u64,
+    }
+
+    fn spawn_sink() -> (u16, std::thread::JoinHandle<SinkReport>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let handle = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let request = read_request(&mut reader).unwrap();
+
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+                .unwrap();
+            SinkReport {
+                content_length: request.content_length,
+                chunked: request.chunked,
+                received_bytes: request.body_bytes,
+            }
+        });
+        (port, handle)
+    }
+
+    #[tokio::test]
+    async fn known_size_is_sent_with_content_length() {
+        let (port, sink) = spawn_sink();
+        let options = UploadTestOptions { size_bytes: Some(1_000_000), ..Default::default() };
+
+        let result = upload_with_custom_dns(&format!("http://127.0.0.1:{}/", port), SYSTEM_DNS, 10, &options, 0, None)
+            .await
+            .unwrap();
+        let report = sink.join().unwrap();
+
+        assert_eq!(report.content_length, Some(1_000_000));
+        assert!(!report.chunked);
+        assert_eq!(report.received_bytes, 1_000_000);
+        assert_eq!(result.uploaded_bytes, 1_000_000);
+        assert_eq!(result.stop_reason, Some(DownloadStopReason::Completed));
+    }
+
+    #[tokio::test]
+    async fn unknown_size_is_chunked_and_counts_what_was_sent() {
+        let (port, sink) = spawn_sink();
+        let options = UploadTestOptions::default();
+
+        let result = upload_with_custom_dns(&format!("http://127.0.0.1:{}/", port), SYSTEM_DNS, 10, &options, 0, None)
+            .await
+            .unwrap();
+        let report = sink.join().unwrap();
+
+        assert!(report.chunked);
+        assert_eq!(report.content_length, None);
+        assert_eq!(result.uploaded_bytes, report.received_bytes);
+        assert_eq!(result.uploaded_bytes, DEFAULT_UPLOAD_BYTES);
+    }
+}
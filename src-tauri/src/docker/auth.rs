@@ -0,0 +1,278 @@
+use anyhow::Result;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use super::credentials::RegistryCredentials;
+
+// Registries may omit expires_in; the distribution spec says to assume 60 seconds
+const DEFAULT_TOKEN_LIFETIME: Duration = Duration::from_secs(60);
+// Refresh a little early so a token doesn't expire mid-download
+const TOKEN_EXPIRY_MARGIN: Duration = Duration::from_secs(10);
+// Basic credentials don't expire, but are re-checked now and then in case they were changed
+const BASIC_AUTH_LIFETIME: Duration = Duration::from_secs(60 * 60);
+// client_id the distribution spec asks OAuth clients to identify themselves with
+const OAUTH_CLIENT_ID: &str = "bargozin";
+
+struct CachedAuthorization {
+    authorization: String,
+    expires_at: Instant,
+}
+
+lazy_static::lazy_static! {
+    // `Authorization` values that worked, keyed by (registry URL, repository)
+    static ref AUTHORIZATION_CACHE: Mutex<HashMap<(String, String), CachedAuthorization>> = Mutex::new(HashMap::new());
+}
+
+// Parsed `WWW-Authenticate` challenge from a 401 response
+#[derive(Debug, Clone)]
+pub struct AuthChallenge {
+    pub scheme: String,
+    pub params: HashMap<String, String>,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    token: Option<String>,
+    access_token: Option<String>,
+    expires_in: Option<u64>,
+}
+
+// Parse e.g. `Bearer realm="https://auth.docker.io/token",service="registry.docker.io",scope="repository:library/ubuntu:pull"`
+pub fn parse_www_authenticate(header: &str) -> Option<AuthChallenge> {
+    let header = header.trim();
+    let (scheme, rest) = header.split_once(' ').unwrap_or((header, ""));
+    if scheme.is_empty() {
+        return None;
+    }
+
+    let mut params = HashMap::new();
+    let mut chars = rest.chars().peekable();
+    loop {
+        while chars.next_if(|c| *c == ',' || c.is_whitespace()).is_some() {}
+
+        let key: String = std::iter::from_fn(|| chars.next_if(|c| *c != '=')).collect();
+        if key.is_empty() || chars.next() != Some('=') {
+            break;
+        }
+
+        // Quoted values may contain commas, e.g. scopes with several actions
+        let value: String = if chars.next_if_eq(&'"').is_some() {
+            let mut value = String::new();
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' => value.extend(chars.next()),
+                    '"' => break,
+                    c => value.push(c),
+                }
+            }
+            value
+        } else {
+            std::iter::from_fn(|| chars.next_if(|c| *c != ',')).collect()
+        };
+        params.insert(key.trim().to_ascii_lowercase(), value.trim().to_string());
+    }
+
+    Some(AuthChallenge { scheme: scheme.to_string(), params })
+}
+
+// `Authorization` value to send to a registry for a repository, if one is cached
+fn cached_authorization(registry_url: &str, repository: &str) -> Option<String> {
+    let cache = AUTHORIZATION_CACHE.lock().unwrap();
+    cache
+        .get(&(registry_url.to_string(), repository.to_string()))
+        .filter(|cached| cached.expires_at > Instant::now())
+        .map(|cached| cached.authorization.clone())
+}
+
+fn cache_authorization(registry_url: &str, repository: &str, authorization: &str, lifetime: Duration) {
+    AUTHORIZATION_CACHE.lock().unwrap().insert(
+        (registry_url.to_string(), repository.to_string()),
+        CachedAuthorization {
+            authorization: authorization.to_string(),
+            expires_at: Instant::now() + lifetime.saturating_sub(TOKEN_EXPIRY_MARGIN),
+        },
+    );
+}
+
+// Ask the challenge's realm for a pull-scoped token, logging in when credentials are available
+fn fetch_pull_token(
+    agent: &ureq::Agent,
+    challenge: &AuthChallenge,
+    repository: &str,
+    credentials: Option<&RegistryCredentials>,
+) -> Result<(String, Duration)> {
+    let realm = challenge
+        .params
+        .get("realm")
+        .ok_or_else(|| anyhow::anyhow!("Registry auth challenge has no realm"))?;
+
+    // Registries don't always echo the scope, so ask for pull access to the repository ourselves
+    let scope = challenge
+        .params
+        .get("scope")
+        .cloned()
+        .unwrap_or_else(|| format!("repository:{}:pull", repository));
+    let service = challenge.params.get("service").map(String::as_str).unwrap_or("");
+    println!("Requesting registry token from {} (scope: {})", realm, scope);
+
+    let response = match credentials {
+        // Identity tokens are exchanged through the OAuth2 endpoint at the same realm
+        Some(RegistryCredentials { identity_token: Some(identity_token), .. }) => agent.post(realm).send_form(&[
+            ("grant_type", "refresh_token"),
+            ("refresh_token", identity_token),
+            ("service", service),
+            ("scope", &scope),
+            ("client_id", OAUTH_CLIENT_ID),
+        ]),
+        _ => {
+            let mut request = agent.get(realm).query("scope", &scope);
+            if !service.is_empty() {
+                request = request.query("service", service);
+            }
+            if let Some(authorization) = credentials.and_then(RegistryCredentials::basic_authorization) {
+                request = request.set("Authorization", &authorization);
+            }
+            request.call()
+        }
+    }
+    .map_err(|e| match e {
+        ureq::Error::Status(401, _) => anyhow::anyhow!("Registry login rejected by {}", realm),
+        e => anyhow::anyhow!("Token request to {} failed: {}", realm, e),
+    })?;
+
+    let response: TokenResponse = serde_json::from_reader(response.into_reader())?;
+    let token = response
+        .token
+        .or(response.access_token)
+        .filter(|token| !token.is_empty())
+        .ok_or_else(|| anyhow::anyhow!("Token response from {} has no token", realm))?;
+
+    let lifetime = response.expires_in.map_or(DEFAULT_TOKEN_LIFETIME, Duration::from_secs);
+    Ok((token, lifetime))
+}
+
+// GET a registry URL, answering Basic or Bearer challenges with the given credentials and retrying once
+pub fn registry_get(
+    agent: &ureq::Agent,
+    url: &str,
+    accept: Option<&str>,
+    registry_url: &str,
+    repository: &str,
+    credentials: Option<&RegistryCredentials>,
+) -> Result<ureq::Response> {
+    let send = |authorization: Option<&str>| {
+        let mut request = agent.get(url);
+        if let Some(accept) = accept {
+            request = request.set("Accept", accept);
+        }
+        if let Some(authorization) = authorization {
+            request = request.set("Authorization", authorization);
+        }
+        request.call().map_err(Box::new)
+    };
+
+    // A configured registry token is used up front; username/password wait for a challenge
+    let initial_authorization = cached_authorization(registry_url, repository).or_else(|| {
+        credentials
+            .and_then(|credentials| credentials.token.as_ref())
+            .map(|token| format!("Bearer {}", token))
+    });
+    let error = match send(initial_authorization.as_deref()) {
+        Ok(response) => return Ok(response),
+        Err(error) => *error,
+    };
+
+    let response = match error {
+        ureq::Error::Status(401, response) => response,
+        e => return Err(e.into()),
+    };
+    // 401s stay ureq errors underneath so callers can still tell the status apart
+    let Some(challenge) = response.header("www-authenticate").and_then(parse_www_authenticate) else {
+        return Err(anyhow::Error::new(ureq::Error::Status(401, response))
+            .context(format!("HTTP error 401 without an auth challenge: {}", url)));
+    };
+
+    let (authorization, lifetime) = if challenge.scheme.eq_ignore_ascii_case("bearer") {
+        let (token, lifetime) = fetch_pull_token(agent, &challenge, repository, credentials)?;
+        (format!("Bearer {}", token), lifetime)
+    } else if challenge.scheme.eq_ignore_ascii_case("basic") {
+        let authorization = credentials
+            .and_then(RegistryCredentials::basic_authorization)
+            .ok_or_else(|| anyhow::anyhow!("Registry requires a username and password: {}", url))?;
+        (authorization, BASIC_AUTH_LIFETIME)
+    } else {
+        return Err(anyhow::anyhow!(
+            "Registry requires unsupported {} authentication: {}",
+            challenge.scheme,
+            url
+        ));
+    };
+
+    let response = send(Some(&authorization)).map_err(|e| match *e {
+        e @ ureq::Error::Status(401, _) => {
+            anyhow::Error::new(e).context(format!("Registry rejected the credentials: {}", url))
+        }
+        // Kept as a ureq error so callers can tell e.g. a missing manifest apart
+        e @ ureq::Error::Status(..) => e.into(),
+        e => anyhow::anyhow!("Request failed after authenticating: {}", e),
+    })?;
+    cache_authorization(registry_url, repository, &authorization, lifetime);
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_docker_hub_bearer_challenge() {
+        let challenge = parse_www_authenticate(
+            r#"Bearer realm="https://auth.docker.io/token",service="registry.docker.io",scope="repository:library/ubuntu:pull""#,
+        )
+        .unwrap();
+
+        assert_eq!(challenge.scheme, "Bearer");
+        assert_eq!(challenge.params["realm"], "https://auth.docker.io/token");
+        assert_eq!(challenge.params["service"], "registry.docker.io");
+        assert_eq!(challenge.params["scope"], "repository:library/ubuntu:pull");
+    }
+
+    #[test]
+    fn quoted_values_keep_commas_and_escapes() {
+        let challenge = parse_www_authenticate(
+            r#"Bearer realm="https://ghcr.io/token", scope="repository:a/b:pull,push", error="say \"hi\"""#,
+        )
+        .unwrap();
+
+        assert_eq!(challenge.params["scope"], "repository:a/b:pull,push");
+        assert_eq!(challenge.params["error"], r#"say "hi""#);
+        assert_eq!(challenge.params.len(), 3);
+    }
+
+    #[test]
+    fn unquoted_values_and_key_case() {
+        let challenge = parse_www_authenticate("Bearer Realm=https://auth.example.com/token,Service=example").unwrap();
+
+        assert_eq!(challenge.params["realm"], "https://auth.example.com/token");
+        assert_eq!(challenge.params["service"], "example");
+    }
+
+    #[test]
+    fn basic_and_bare_schemes() {
+        let basic = parse_www_authenticate(r#"Basic realm="Registry Realm""#).unwrap();
+        assert_eq!(basic.scheme, "Basic");
+        assert_eq!(basic.params["realm"], "Registry Realm");
+
+        let bare = parse_www_authenticate("Bearer").unwrap();
+        assert_eq!(bare.scheme, "Bearer");
+        assert!(bare.params.is_empty());
+    }
+
+    #[test]
+    fn empty_header_is_rejected() {
+        assert!(parse_www_authenticate("").is_none());
+        assert!(parse_www_authenticate("   ").is_none());
+    }
+}
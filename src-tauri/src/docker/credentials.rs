@@ -0,0 +1,215 @@
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// Key the Docker CLI uses for Docker Hub in `auths` and credential helpers
+const DOCKER_HUB_SERVER: &str = "https://index.docker.io/v1/";
+const DOCKER_HUB_ALIASES: &[&str] = &["docker.io", "index.docker.io", "registry-1.docker.io", "registry.hub.docker.com"];
+// A helper waiting on a keychain prompt or hung otherwise is killed after this
+const HELPER_TIMEOUT: Duration = Duration::from_secs(10);
+const HELPER_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+lazy_static::lazy_static! {
+    // Helper answers keyed by (helper, server), misses included, so each is asked at most once per run
+    static ref HELPER_CACHE: Mutex<HashMap<(String, String), Option<RegistryCredentials>>> = Mutex::new(HashMap::new());
+}
+
+#[derive(Clone, Default)]
+pub struct RegistryCredentials {
+    pub username: Option<String>,
+    pub password: Option<String>,
+    // Sent as-is as a Bearer token
+    pub token: Option<String>,
+    // OAuth refresh token the Docker CLI stores after `docker login` to some registries
+    pub identity_token: Option<String>,
+}
+
+// Keep secrets out of the logs
+impl std::fmt::Debug for RegistryCredentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RegistryCredentials")
+            .field("username", &self.username)
+            .field("password", &self.password.as_ref().map(|_| "***"))
+            .field("token", &self.token.as_ref().map(|_| "***"))
+            .field("identity_token", &self.identity_token.as_ref().map(|_| "***"))
+            .finish()
+    }
+}
+
+impl RegistryCredentials {
+    pub fn basic_authorization(&self) -> Option<String> {
+        let username = self.username.as_ref()?;
+        let password = self.password.as_deref().unwrap_or("");
+        Some(format!("Basic {}", BASE64.encode(format!("{}:{}", username, password))))
+    }
+}
+
+// Output of `docker-credential-<helper> get`
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct HelperCredentials {
+    username: String,
+    secret: String,
+}
+
+// Bare `host[:port]` for comparing registry URLs and config keys; Docker Hub aliases collapse to docker.io
+pub fn registry_host(registry: &str) -> String {
+    let without_scheme = registry.split_once("://").map_or(registry, |(_, rest)| rest);
+    let host = without_scheme.split('/').next().unwrap_or("").to_ascii_lowercase();
+    if DOCKER_HUB_ALIASES.contains(&host.as_str()) {
+        "docker.io".to_string()
+    } else {
+        host
+    }
+}
+
+fn docker_cli_config_path() -> Option<PathBuf> {
+    match std::env::var_os("DOCKER_CONFIG") {
+        Some(dir) => Some(PathBuf::from(dir).join("config.json")),
+        None => dirs::home_dir().map(|home| home.join(".docker").join("config.json")),
+    }
+}
+
+fn credentials_from_auth_entry(entry: &serde_json::Value) -> Option<RegistryCredentials> {
+    let field = |name: &str| entry[name].as_str().filter(|value| !value.is_empty()).map(String::from);
+
+    let mut credentials = RegistryCredentials {
+        username: field("username"),
+        password: field("password"),
+        token: field("registrytoken"),
+        identity_token: field("identitytoken"),
+    };
+
+    // `auth` is base64("username:password") and is what `docker login` usually writes
+    if let Some(decoded) = field("auth")
+        .and_then(|auth| BASE64.decode(auth).ok())
+        .and_then(|bytes| String::from_utf8(bytes).ok())
+    {
+        if let Some((username, password)) = decoded.split_once(':') {
+            credentials.username.get_or_insert_with(|| username.to_string());
+            credentials.password.get_or_insert_with(|| password.to_string());
+        }
+    }
+
+    let has_any = credentials.username.is_some() || credentials.token.is_some() || credentials.identity_token.is_some();
+    has_any.then_some(credentials)
+}
+
+// Forget helper answers from the previous run so a fresh `docker login` is picked up
+pub fn clear_credential_helper_cache() {
+    HELPER_CACHE.lock().unwrap().clear();
+}
+
+fn credentials_from_helper(helper: &str, server: &str) -> Option<RegistryCredentials> {
+    let key = (helper.to_string(), server.to_string());
+    if let Some(cached) = HELPER_CACHE.lock().unwrap().get(&key) {
+        return cached.clone();
+    }
+    let credentials = run_credential_helper(helper, server);
+    HELPER_CACHE.lock().unwrap().insert(key, credentials.clone());
+    credentials
+}
+
+fn run_credential_helper(helper: &str, server: &str) -> Option<RegistryCredentials> {
+    let program = format!("docker-credential-{}", helper);
+    let mut child = match Command::new(&program)
+        .arg("get")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+    {
+        Ok(child) => child,
+        Err(e) => {
+            println!("Failed to run credential helper {}: {}", program, e);
+            return None;
+        }
+    };
+
+    if let Some(mut stdin) = child.stdin.take() {
+        let _ = stdin.write_all(server.as_bytes());
+    }
+
+    let deadline = Instant::now() + HELPER_TIMEOUT;
+    loop {
+        match child.try_wait() {
+            Ok(Some(_)) => break,
+            Ok(None) if Instant::now() < deadline => std::thread::sleep(HELPER_POLL_INTERVAL),
+            Ok(None) => {
+                println!("Credential helper {} timed out after {:?}", program, HELPER_TIMEOUT);
+                let _ = child.kill();
+                let _ = child.wait();
+                return None;
+            }
+            Err(e) => {
+                println!("Failed to wait for credential helper {}: {}", program, e);
+                return None;
+            }
+        }
+    }
+    let output = child.wait_with_output().ok()?;
+    if !output.status.success() {
+        println!("Credential helper {} has no credentials for {}", program, server);
+        return None;
+    }
+
+    let helper_credentials: HelperCredentials = serde_json::from_slice(&output.stdout).ok()?;
+    // A username of "<token>" marks the secret as an identity token rather than a password
+    Some(if helper_credentials.username == "<token>" {
+        RegistryCredentials {
+            identity_token: Some(helper_credentials.secret),
+            ..Default::default()
+        }
+    } else {
+        RegistryCredentials {
+            username: Some(helper_credentials.username),
+            password: Some(helper_credentials.secret),
+            ..Default::default()
+        }
+    })
+}
+
+// Credentials `docker login` stored for a registry: `auths` first, then `credHelpers`, then `credsStore`.
+// Helpers are only asked about registries the config lists. Blocking: credential helpers are external programs
+pub fn docker_cli_credentials(registry: &str) -> Option<RegistryCredentials> {
+    let path = docker_cli_config_path()?;
+    let content = std::fs::read_to_string(&path).ok()?;
+    let config: serde_json::Value = match serde_json::from_str(&content) {
+        Ok(config) => config,
+        Err(e) => {
+            println!("Failed to parse {}: {}", path.display(), e);
+            return None;
+        }
+    };
+
+    let host = registry_host(registry);
+    let matching_key = |section: &str| {
+        config[section]
+            .as_object()
+            .and_then(|entries| entries.iter().find(|(key, _)| registry_host(key) == host))
+            .map(|(key, value)| (key.clone(), value.clone()))
+    };
+
+    let auth_entry = matching_key("auths");
+    if let Some(credentials) = auth_entry.as_ref().and_then(|(_, entry)| credentials_from_auth_entry(entry)) {
+        println!("Using Docker CLI credentials for {}", host);
+        return Some(credentials);
+    }
+
+    let server = if host == "docker.io" { DOCKER_HUB_SERVER.to_string() } else { host.clone() };
+    // With a credsStore, `docker login` leaves an empty `auths` entry for every registry it stored
+    let helper = match matching_key("credHelpers") {
+        Some((_, helper)) => helper.as_str().map(String::from)?,
+        None if auth_entry.is_some() => config["credsStore"].as_str().map(String::from)?,
+        None => return None,
+    };
+    let credentials = credentials_from_helper(&helper, &server)?;
+    println!("Using credentials from docker-credential-{} for {}", helper, host);
+    Some(credentials)
+}
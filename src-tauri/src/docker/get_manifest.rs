@@ -4,11 +4,20 @@ use std::time::Duration;
 use anyhow::Result;
 use std::io::Read;
 
+use super::auth::registry_get;
+use super::credentials::RegistryCredentials;
+
+// Accepted for tags: multi-platform indexes as well as single image manifests, Docker or OCI
+const TAG_ACCEPT: &str = "application/vnd.docker.distribution.manifest.list.v2+json,application/vnd.docker.distribution.manifest.v2+json,application/vnd.oci.image.index.v1+json,application/vnd.oci.image.manifest.v1+json";
+const IMAGE_MANIFEST_ACCEPT: &str = "application/vnd.docker.distribution.manifest.v2+json,application/vnd.oci.image.manifest.v1+json";
+
+// Docker manifest list or OCI image index. OCI makes `mediaType` optional, so it falls back to the Content-Type
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ManifestList {
+    #[serde(default)]
     pub schema_version: u32,
-    pub media_type: String,
+    pub media_type: Option<String>,
     pub manifests: Vec<Manifest>,
 }
 
@@ -16,9 +25,11 @@ pub struct ManifestList {
 #[serde(rename_all = "camelCase")]
 pub struct Manifest {
     pub digest: String,
-    pub media_type: String,
+    pub media_type: Option<String>,
+    #[serde(default)]
     pub size: u64,
-    pub platform: Platform,
+    // OCI indexes may list platform-independent entries without one
+    pub platform: Option<Platform>,
     pub annotations: Option<HashMap<String, String>>,
 }
 
@@ -29,11 +40,136 @@ pub struct Platform {
     pub variant: Option<String>,
 }
 
+impl std::fmt::Display for Platform {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.os, self.architecture)?;
+        if let Some(variant) = &self.variant {
+            write!(f, "/{}", variant)?;
+        }
+        Ok(())
+    }
+}
+
+// Platform to pick from a multi-platform image, in Docker's `os/arch[/variant]` terms
+#[derive(Debug, Clone, PartialEq)]
+pub struct TargetPlatform {
+    pub os: String,
+    pub architecture: String,
+    pub variant: Option<String>,
+}
+
+impl TargetPlatform {
+    // Docker Desktop runs Linux containers on macOS and Windows too, so only the architecture comes from the host
+    pub fn host() -> Self {
+        let (architecture, variant) = match std::env::consts::ARCH {
+            "x86_64" => ("amd64", None),
+            "x86" => ("386", None),
+            "aarch64" => ("arm64", None),
+            "arm" => ("arm", Some("v7")),
+            "powerpc64" => ("ppc64le", None),
+            arch => (arch, None),
+        };
+        Self {
+            os: "linux".to_string(),
+            architecture: architecture.to_string(),
+            variant: variant.map(String::from),
+        }
+    }
+
+    // Parse e.g. "linux/amd64" or "linux/arm/v7"
+    pub fn parse(platform: &str) -> Result<Self> {
+        let parts: Vec<&str> = platform.trim().split('/').collect();
+        match parts.as_slice() {
+            [os, architecture] | [os, architecture, _]
+                if !os.is_empty() && !architecture.is_empty() =>
+            {
+                Ok(Self {
+                    os: os.to_ascii_lowercase(),
+                    architecture: architecture.to_ascii_lowercase(),
+                    variant: parts.get(2).filter(|v| !v.is_empty()).map(|v| v.to_ascii_lowercase()),
+                })
+            }
+            _ => Err(anyhow::anyhow!("Invalid platform {:?}, expected os/arch[/variant]", platform)),
+        }
+    }
+}
+
+impl std::fmt::Display for TargetPlatform {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.os, self.architecture)?;
+        if let Some(variant) = &self.variant {
+            write!(f, "/{}", variant)?;
+        }
+        Ok(())
+    }
+}
+
+// Buildkit stores provenance/SBOM attestations in the index as unknown/unknown entries
+fn is_attestation(manifest: &Manifest) -> bool {
+    let reference_type = manifest
+        .annotations
+        .as_ref()
+        .and_then(|annotations| annotations.get("vnd.docker.reference.type"));
+    let unknown_platform = manifest
+        .platform
+        .as_ref()
+        .is_some_and(|platform| platform.os == "unknown" && platform.architecture == "unknown");
+    reference_type.is_some_and(|t| t == "attestation-manifest") || unknown_platform
+}
+
+// arm64 images usually leave the variant out, meaning v8
+fn normalized_variant<'a>(architecture: &str, variant: Option<&'a str>) -> Option<&'a str> {
+    match (architecture, variant) {
+        ("arm64", None) => Some("v8"),
+        (_, variant) => variant,
+    }
+}
+
+// Pick the entry for `target`, preferring an exact variant match over any variant of the architecture;
+// entries without a platform are only used when nothing matches
+pub fn select_platform_manifest<'a>(manifest_list: &'a ManifestList, target: &TargetPlatform) -> Result<&'a Manifest> {
+    let images: Vec<&Manifest> = manifest_list
+        .manifests
+        .iter()
+        .filter(|manifest| !is_attestation(manifest))
+        .collect();
+    let candidates: Vec<(&Manifest, &Platform)> = images
+        .iter()
+        .filter_map(|manifest| manifest.platform.as_ref().map(|platform| (*manifest, platform)))
+        .filter(|(_, platform)| {
+            platform.os.eq_ignore_ascii_case(&target.os)
+                && platform.architecture.eq_ignore_ascii_case(&target.architecture)
+        })
+        .collect();
+
+    let target_variant = normalized_variant(&target.architecture, target.variant.as_deref());
+    let exact = candidates.iter().find(|(_, platform)| {
+        normalized_variant(&platform.architecture, platform.variant.as_deref()) == target_variant
+    });
+    let platform_independent = images.iter().find(|manifest| manifest.platform.is_none());
+
+    match exact.or(candidates.first()).map(|(manifest, _)| manifest).or(platform_independent) {
+        Some(manifest) => Ok(manifest),
+        None => {
+            let available: Vec<String> = images
+                .iter()
+                .filter_map(|manifest| manifest.platform.as_ref().map(Platform::to_string))
+                .collect();
+            Err(anyhow::anyhow!(
+                "No manifest for platform {} (available: {})",
+                target,
+                available.join(", ")
+            ))
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct DigestManifest {
+    #[serde(default)]
     pub schema_version: u32,
-    pub media_type: String,
+    pub media_type: Option<String>,
     pub config: ConfigDescriptor,
     pub layers: Vec<LayerDescriptor>,
 }
@@ -41,7 +177,8 @@ pub struct DigestManifest {
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ConfigDescriptor {
-    pub media_type: String,
+    pub media_type: Option<String>,
+    #[serde(default)]
     pub size: u64,
     pub digest: String,
 }
@@ -49,11 +186,30 @@ pub struct ConfigDescriptor {
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct LayerDescriptor {
-    pub media_type: String,
+    pub media_type: Option<String>,
+    #[serde(default)]
     pub size: u64,
     pub digest: String,
 }
 
+// Media type without parameters such as `charset`
+fn content_type(response: &ureq::Response) -> Option<String> {
+    response
+        .header("content-type")
+        .map(|value| value.split(';').next().unwrap_or("").trim().to_string())
+        .filter(|value| !value.is_empty())
+}
+
+// Registry answered 404 for the manifest, or 401/403 as Docker Hub and Harbor do for a repository that doesn't exist
+pub fn is_manifest_unavailable(error: &anyhow::Error) -> bool {
+    matches!(error.downcast_ref::<ureq::Error>(), Some(ureq::Error::Status(401 | 403 | 404, _)))
+}
+
+// Registry refused access (401/403) rather than reporting the manifest missing
+pub fn is_access_denied(error: &anyhow::Error) -> bool {
+    matches!(error.downcast_ref::<ureq::Error>(), Some(ureq::Error::Status(401 | 403, _)))
+}
+
 // Create a configured HTTP client
 fn create_http_client() -> Result<ureq::Agent> {
     let agent = ureq::AgentBuilder::new()
@@ -64,27 +220,32 @@ fn create_http_client() -> Result<ureq::Agent> {
 }
 
 /// Fetch and parse a manifest list from a registry URL
-pub fn fetch_tag_manifest(registry_url: &str, image_name: &str, tag: &str) -> Result<ManifestList> {
+pub fn fetch_tag_manifest(registry_url: &str, image_name: &str, tag: &str, credentials: Option<&RegistryCredentials>) -> Result<ManifestList> {
     let agent = create_http_client()?;
     let url = format!("{}/v2/{}/manifests/{}", registry_url, image_name, tag);
     
-    let response = agent
-        .get(&url)
-        .set("Accept", "application/vnd.docker.distribution.manifest.list.v2+json,application/vnd.docker.distribution.manifest.v2+json,application/vnd.oci.image.index.v1+json,application/vnd.oci.image.manifest.v1+json")
-        .call()?;
+    let response = registry_get(
+        &agent,
+        &url,
+        Some(TAG_ACCEPT),
+        registry_url,
+        image_name,
+        credentials,
+    )?;
 
     if response.status() != 200 {
         return Err(anyhow::anyhow!("HTTP error {}: {}", response.status(), url));
     }
 
     // Get headers before consuming response
-    let _content_type = response.header("content-type").unwrap_or("");
+    let content_type = content_type(&response);
 
     let mut response_text = String::new();
     response.into_reader().read_to_string(&mut response_text)?;
     
     // Try to parse as ManifestList first
-    if let Ok(manifest_list) = serde_json::from_str::<ManifestList>(&response_text) {
+    if let Ok(mut manifest_list) = serde_json::from_str::<ManifestList>(&response_text) {
+        manifest_list.media_type = manifest_list.media_type.or(content_type);
         return Ok(manifest_list);
     }
     
@@ -92,26 +253,146 @@ pub fn fetch_tag_manifest(registry_url: &str, image_name: &str, tag: &str) -> Re
     // Return an empty ManifestList to indicate we should try direct manifest fetch
     Ok(ManifestList {
         schema_version: 2,
-        media_type: "application/vnd.docker.distribution.manifest.list.v2+json".to_string(),
+        media_type: None,
         manifests: vec![], // Empty manifests array signals to try direct fetch
     })
 }
 
-pub fn fetch_digest_manifest(registry_url: &str, image_name: &str, digest: &str) -> Result<DigestManifest> {
+pub fn fetch_digest_manifest(registry_url: &str, image_name: &str, digest: &str, credentials: Option<&RegistryCredentials>) -> Result<DigestManifest> {
     let agent = create_http_client()?;
     let url = format!("{}/v2/{}/manifests/{}", registry_url, image_name, digest);
     
-    let response = agent
-        .get(&url)
-        .set("Accept", "application/vnd.docker.distribution.manifest.v2+json")
-        .call()?;
+    let response = registry_get(
+        &agent,
+        &url,
+        Some(IMAGE_MANIFEST_ACCEPT),
+        registry_url,
+        image_name,
+        credentials,
+    )?;
 
     if response.status() != 200 {
         return Err(anyhow::anyhow!("HTTP error {}: {}", response.status(), url));
     }
 
+    let content_type = content_type(&response);
+
     let mut response_text = String::new();
     response.into_reader().read_to_string(&mut response_text)?;
-    let digest_manifest: DigestManifest = serde_json::from_str(&response_text)?;
+    let mut digest_manifest: DigestManifest = serde_json::from_str(&response_text)?;
+    digest_manifest.media_type = digest_manifest.media_type.or(content_type);
     Ok(digest_manifest)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manifest_list(entries: serde_json::Value) -> ManifestList {
+        serde_json::from_value(serde_json::json!({ "schemaVersion": 2, "manifests": entries })).unwrap()
+    }
+
+    fn entry(digest: &str, os: &str, architecture: &str, variant: Option<&str>) -> serde_json::Value {
+        let mut platform = serde_json::json!({ "os": os, "architecture": architecture });
+        if let Some(variant) = variant {
+            platform["variant"] = variant.into();
+        }
+        serde_json::json!({ "digest": digest, "platform": platform })
+    }
+
+    fn select(list: &ManifestList, target: &str) -> Result<String> {
+        select_platform_manifest(list, &TargetPlatform::parse(target).unwrap()).map(|manifest| manifest.digest.clone())
+    }
+
+    #[test]
+    fn parses_platforms() {
+        let platform = TargetPlatform::parse(" Linux/ARM/v7 ").unwrap();
+        assert_eq!(platform.os, "linux");
+        assert_eq!(platform.architecture, "arm");
+        assert_eq!(platform.variant.as_deref(), Some("v7"));
+        assert_eq!(platform.to_string(), "linux/arm/v7");
+
+        assert_eq!(TargetPlatform::parse("linux/amd64").unwrap().variant, None);
+        assert_eq!(TargetPlatform::parse("linux/arm64/").unwrap().variant, None);
+    }
+
+    #[test]
+    fn rejects_malformed_platforms() {
+        for platform in ["", "linux", "/amd64", "linux/", "linux/arm/v7/extra"] {
+            assert!(TargetPlatform::parse(platform).is_err(), "{:?} should be rejected", platform);
+        }
+    }
+
+    #[test]
+    fn prefers_exact_variant() {
+        let list = manifest_list(serde_json::json!([
+            entry("sha256:amd64", "linux", "amd64", None),
+            entry("sha256:armv6", "linux", "arm", Some("v6")),
+            entry("sha256:armv7", "linux", "arm", Some("v7")),
+        ]));
+
+        assert_eq!(select(&list, "linux/arm/v7").unwrap(), "sha256:armv7");
+        assert_eq!(select(&list, "linux/arm/v6").unwrap(), "sha256:armv6");
+        assert_eq!(select(&list, "linux/amd64").unwrap(), "sha256:amd64");
+    }
+
+    #[test]
+    fn arm64_without_variant_means_v8() {
+        let list = manifest_list(serde_json::json!([
+            entry("sha256:arm64v8", "linux", "arm64", Some("v8")),
+            entry("sha256:arm64v9", "linux", "arm64", Some("v9")),
+        ]));
+        assert_eq!(select(&list, "linux/arm64").unwrap(), "sha256:arm64v8");
+
+        let list = manifest_list(serde_json::json!([
+            entry("sha256:arm64v9", "linux", "arm64", Some("v9")),
+            entry("sha256:arm64", "linux", "arm64", None),
+        ]));
+        assert_eq!(select(&list, "linux/arm64/v8").unwrap(), "sha256:arm64");
+    }
+
+    #[test]
+    fn falls_back_to_first_variant_of_the_architecture() {
+        let list = manifest_list(serde_json::json!([
+            entry("sha256:armv6", "linux", "arm", Some("v6")),
+            entry("sha256:armv7", "linux", "arm", Some("v7")),
+        ]));
+        assert_eq!(select(&list, "linux/arm/v5").unwrap(), "sha256:armv6");
+    }
+
+    #[test]
+    fn skips_attestations() {
+        let mut attestation = entry("sha256:attestation", "linux", "amd64", None);
+        attestation["annotations"] = serde_json::json!({ "vnd.docker.reference.type": "attestation-manifest" });
+        let list = manifest_list(serde_json::json!([
+            attestation,
+            entry("sha256:unknown", "unknown", "unknown", None),
+            entry("sha256:amd64", "linux", "amd64", None),
+        ]));
+        assert_eq!(select(&list, "linux/amd64").unwrap(), "sha256:amd64");
+
+        let error = select(&list, "linux/s390x").unwrap_err().to_string();
+        assert!(error.contains("available: linux/amd64"), "{}", error);
+    }
+
+    #[test]
+    fn platform_independent_entry_is_the_last_resort() {
+        let list = manifest_list(serde_json::json!([
+            { "digest": "sha256:any" },
+            entry("sha256:amd64", "linux", "amd64", None),
+        ]));
+        assert_eq!(select(&list, "linux/amd64").unwrap(), "sha256:amd64");
+        assert_eq!(select(&list, "windows/amd64").unwrap(), "sha256:any");
+    }
+
+    #[test]
+    fn no_match_lists_available_platforms() {
+        let list = manifest_list(serde_json::json!([
+            entry("sha256:amd64", "linux", "amd64", None),
+            entry("sha256:armv7", "linux", "arm", Some("v7")),
+        ]));
+
+        let error = select(&list, "linux/riscv64").unwrap_err().to_string();
+        assert_eq!(error, "No manifest for platform linux/riscv64 (available: linux/amd64, linux/arm/v7)");
+    }
+}
@@ -4,11 +4,19 @@ use std::path::PathBuf;
 use tokio::fs::File;
 use tokio::io::AsyncWriteExt;
 use anyhow::Result;
-use regex::Regex;
 use std::io::Read;
+use crate::usage::{data_budget_exceeded, record_downloaded_bytes, DATA_BUDGET_EXCEEDED};
 
+mod auth;
+mod credentials;
 mod get_manifest;
-use get_manifest::{fetch_tag_manifest, fetch_digest_manifest};
+mod reference;
+use auth::registry_get;
+use credentials::docker_cli_credentials;
+pub use credentials::{clear_credential_helper_cache, RegistryCredentials};
+use get_manifest::{fetch_tag_manifest, fetch_digest_manifest, is_access_denied, is_manifest_unavailable, select_platform_manifest};
+pub use get_manifest::TargetPlatform;
+pub use reference::ImageReference;
 
 pub const DOCKER_CONFIG_URL: &str = "https://raw.githubusercontent.com/403unlocker/403Unlocker-cli/refs/heads/main/config/dockerRegistry.yml";
 
@@ -21,9 +29,20 @@ pub struct DockerRegistryTestResult {
     pub downloaded_bytes: u64,
     pub test_duration_seconds: f64,
     pub error_message: Option<String>,
+    // Media type of the image manifest whose layer was downloaded, Docker or OCI
+    pub manifest_media_type: Option<String>,
+    // Set when the tag pointed at a manifest list or OCI index
+    pub index_media_type: Option<String>,
     pub session_id: u64,
 }
 
+// Media types seen while resolving an image to one of its layers
+#[derive(Debug, Clone, Default)]
+struct ManifestMediaTypes {
+    index: Option<String>,
+    manifest: Option<String>,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct DockerRegistryBulkTestResult {
     pub image_name: String,
@@ -35,24 +54,52 @@ pub struct DockerRegistryBulkTestResult {
     pub best_speed_mbps: f64,
 }
 
+// A registryList entry: either a plain host or a mapping with credentials, e.g.
+// `{ url: registry.company.com, username: ci, password: secret }` or `{ url: ..., token: ... }`
+#[derive(Debug, Clone)]
+pub struct RegistryEntry {
+    pub registry: String,
+    pub credentials: Option<RegistryCredentials>,
+}
+
 // Config management functions
 pub fn docker_config_path() -> PathBuf {
     let config_dir = dirs::config_dir().unwrap_or_else(|| PathBuf::from("."));
     config_dir.join("bargozin").join("dockerRegistry.yml")
 }
 
-pub async fn read_docker_registries_file(path: &PathBuf) -> Result<Vec<String>> {
+pub async fn read_docker_registries_file(path: &PathBuf) -> Result<Vec<RegistryEntry>> {
     let content = tokio::fs::read_to_string(path).await?;
     let yaml: serde_yaml::Value = serde_yaml::from_str(&content)?;
     let list = yaml["registryList"]
         .as_sequence()
         .ok_or_else(|| anyhow::anyhow!("registryList key missing"))?
         .iter()
-        .filter_map(|v| v.as_str().map(String::from))
+        .filter_map(registry_entry_from_yaml)
         .collect();
     Ok(list)
 }
 
+fn registry_entry_from_yaml(value: &serde_yaml::Value) -> Option<RegistryEntry> {
+    if let Some(registry) = value.as_str() {
+        return Some(RegistryEntry { registry: registry.to_string(), credentials: None });
+    }
+
+    let field = |name: &str| value[name].as_str().map(String::from);
+    let registry = field("url").or_else(|| field("registry"))?;
+    let credentials = RegistryCredentials {
+        username: field("username"),
+        password: field("password"),
+        token: field("token"),
+        identity_token: None,
+    };
+    let has_credentials = credentials.username.is_some() || credentials.token.is_some();
+    Some(RegistryEntry {
+        registry,
+        credentials: has_credentials.then_some(credentials),
+    })
+}
+
 pub async fn download_docker_config_file(url: &str, path: &PathBuf) -> Result<()> {
     let content = tokio::task::spawn_blocking({
         let url = url.to_string();
@@ -72,16 +119,14 @@ pub async fn download_docker_config_file(url: &str, path: &PathBuf) -> Result<()
     Ok(())
 }
 
-// Docker image name validation
-pub fn validate_docker_image_name(image_name: &str) -> bool {
-    // Pattern similar to Go code
-    let pattern = r"^(?:[a-zA-Z0-9\-._]+(?::[0-9]+)?/)?(?:[a-z0-9\-._]+/)?[a-z0-9\-._]+(?::[a-zA-Z0-9\-._]+)?(?:@[a-zA-Z0-9\-._:]+)?$";
-    let regex = Regex::new(pattern).unwrap();
-    regex.is_match(image_name) && !image_name.contains("@@")
-}
-
 // Download function using ureq - returns downloaded bytes even on timeout
-pub fn download_with_ureq(url: &str, max_duration: Duration) -> Result<u64> {
+pub fn download_with_ureq(
+    url: &str,
+    max_duration: Duration,
+    registry_url: &str,
+    repository: &str,
+    credentials: Option<&RegistryCredentials>,
+) -> Result<u64> {
     let start_time = Instant::now();
     println!("Starting download from: {}", url);
     
@@ -90,7 +135,8 @@ pub fn download_with_ureq(url: &str, max_duration: Duration) -> Result<u64> {
         .user_agent("registry-speed-tester/0.1")
         .build();
 
-    let response = agent.get(url).call()?;
+    // Reuses the authorization cached while fetching manifests
+    let response = registry_get(&agent, url, None, registry_url, repository, credentials)?;
     
     if response.status() != 200 {
         return Err(anyhow::anyhow!("HTTP error: {}", response.status()));
@@ -118,6 +164,12 @@ pub fn download_with_ureq(url: &str, max_duration: Duration) -> Result<u64> {
             }
             Ok(n) => {
                 total_bytes += n as u64;
+                record_downloaded_bytes(n as u64);
+
+                if data_budget_exceeded() {
+                    println!("Data budget exceeded after downloading {} bytes", total_bytes);
+                    break;
+                }
 
                 // Log progress every second
                 if last_log_time.elapsed() >= Duration::from_secs(1) {
@@ -153,11 +205,12 @@ pub async fn test_docker_registry_download_speed(
     registry: &str,
     image_name: &str,
     timeout_seconds: u64,
+    credentials: Option<&RegistryCredentials>,
+    platform: &TargetPlatform,
 ) -> DockerRegistryTestResult {
     let start_time = Instant::now();
-    
-    // Validate image name
-    if !validate_docker_image_name(image_name) {
+
+    if data_budget_exceeded() {
         return DockerRegistryTestResult {
             registry: registry.to_string(),
             image_name: image_name.to_string(),
@@ -165,13 +218,32 @@ pub async fn test_docker_registry_download_speed(
             download_speed_mbps: 0.0,
             downloaded_bytes: 0,
             test_duration_seconds: 0.0,
-            error_message: Some("Invalid Docker image name format".to_string()),
-            session_id: 0, // No longer using sessions
+            error_message: Some(DATA_BUDGET_EXCEEDED.to_string()),
+            manifest_media_type: None,
+            index_media_type: None,
+            session_id: 0,
         };
     }
+    
+    let reference = match ImageReference::parse(image_name) {
+        Ok(reference) => reference,
+        Err(e) => return DockerRegistryTestResult {
+            registry: registry.to_string(),
+            image_name: image_name.to_string(),
+            success: false,
+            download_speed_mbps: 0.0,
+            downloaded_bytes: 0,
+            test_duration_seconds: 0.0,
+            error_message: Some(format!("Invalid Docker image name: {}", e)),
+            manifest_media_type: None,
+            index_media_type: None,
+            session_id: 0, // No longer using sessions
+        },
+    };
 
-    // Parse image name to extract repository and tag
-    let (repository, tag) = parse_image_name(image_name);
+    // The image's own registry, if it names one, isn't part of the path requested from each registry
+    let repositories = reference.repository_candidates();
+    let tag = reference.manifest_reference().to_string();
     
     // Build registry URL
     let registry_url = if registry.contains("://") {
@@ -182,9 +254,20 @@ pub async fn test_docker_registry_download_speed(
 
     let download_duration = Duration::from_secs(timeout_seconds); // Enforce user's timeout
 
+    // Credentials from the registry list win over whatever `docker login` stored
+    let credentials = match credentials {
+        Some(credentials) => Some(credentials.clone()),
+        None => {
+            let registry = registry.to_string();
+            tokio::task::spawn_blocking(move || docker_cli_credentials(&registry))
+                .await
+                .unwrap_or_default()
+        }
+    };
+
     // Try the blob-based download approach
-    match test_registry_with_manifest_approach(&registry_url, &repository, &tag, download_duration).await {
-        Ok(downloaded_bytes) => {
+    match test_registry_with_manifest_approach(&registry_url, &repositories, &tag, download_duration, credentials, platform).await {
+        Ok((downloaded_bytes, media_types)) => {
             let elapsed = start_time.elapsed().as_secs_f64();
             let speed_mbps = if elapsed > 0.0 {
                 (downloaded_bytes as f64 * 8.0) / (elapsed * 1_000_000.0)
@@ -204,6 +287,8 @@ pub async fn test_docker_registry_download_speed(
                     downloaded_bytes,
                     test_duration_seconds: elapsed,
                     error_message: None,
+                    manifest_media_type: media_types.manifest,
+                    index_media_type: media_types.index,
                     session_id: 0, // No longer using sessions
                 }
             } else {
@@ -217,6 +302,8 @@ pub async fn test_docker_registry_download_speed(
                     downloaded_bytes: 0,
                     test_duration_seconds: elapsed,
                     error_message: Some("No data downloaded".to_string()),
+                    manifest_media_type: media_types.manifest,
+                    index_media_type: media_types.index,
                     session_id: 0, // No longer using sessions
                 }
             }
@@ -233,63 +320,64 @@ pub async fn test_docker_registry_download_speed(
                 downloaded_bytes: 0, // No bytes downloaded on failure
                 test_duration_seconds: elapsed,
                 error_message: Some(e.to_string()),
+                manifest_media_type: None,
+                index_media_type: None,
                 session_id: 0, // No longer using sessions
             }
         }
     }
 }
 
-// Parse image name to extract repository and tag
-fn parse_image_name(image_name: &str) -> (String, String) {
-    if let Some(at_pos) = image_name.find('@') {
-        // Handle digest format (e.g., ubuntu@sha256:...)
-        (image_name[..at_pos].to_string(), "latest".to_string())
-    } else if let Some(colon_pos) = image_name.rfind(':') {
-        // Handle tag format (e.g., ubuntu:latest)
-        let before_colon = &image_name[..colon_pos];
-        let after_colon = &image_name[colon_pos + 1..];
-        
-        // Check if after colon looks like a port number (for registry URLs)
-        if after_colon.chars().all(|c| c.is_ascii_digit()) && before_colon.contains('/') {
-            // This is likely a registry with port, treat whole thing as repository
-            (image_name.to_string(), "latest".to_string())
-        } else {
-            // This is a tag
-            (before_colon.to_string(), after_colon.to_string())
-        }
-    } else {
-        // No tag specified, use latest
-        (image_name.to_string(), "latest".to_string())
-    }
-}
-
 // New manifest-based testing approach
 async fn test_registry_with_manifest_approach(
     registry_url: &str,
-    repository: &str,
+    repositories: &[String],
     tag: &str,
     max_duration: Duration,
-) -> Result<u64> {
+    credentials: Option<RegistryCredentials>,
+    platform: &TargetPlatform,
+) -> Result<(u64, ManifestMediaTypes)> {
     let start_time = Instant::now();
     
-    println!("Testing registry: {} with image: {}:{}", registry_url, repository, tag);
-    
-    // Try to get the actual manifest that contains layer information
-    let layer_digest = match tokio::task::spawn_blocking({
-        let registry_url = registry_url.to_string();
-        let repository = repository.to_string();
-        let tag = tag.to_string();
-        move || get_first_layer_digest(&registry_url, &repository, &tag)
-    }).await? {
-        Ok(digest) => {
-            println!("Got layer digest: {}", digest);
-            digest
-        },
-        Err(e) => {
-            println!("Failed to get layer digest: {}", e);
-            return Err(anyhow::anyhow!("Failed to get layer digest: {}", e));
-        },
-    };
+    // Try to get the actual manifest that contains layer information, moving on to the next path on 401/403/404
+    let mut found = None;
+    let mut attempts = Vec::new();
+    let mut all_denied = true;
+    for repository in repositories {
+        println!("Testing registry: {} with image: {}:{}", registry_url, repository, tag);
+
+        match tokio::task::spawn_blocking({
+            let registry_url = registry_url.to_string();
+            let repository = repository.clone();
+            let tag = tag.to_string();
+            let credentials = credentials.clone();
+            let platform = platform.clone();
+            move || get_first_layer_digest(&registry_url, &repository, &tag, credentials.as_ref(), &platform)
+        }).await? {
+            Ok((digest, media_types)) => {
+                println!("Got layer digest: {} ({:?})", digest, media_types);
+                found = Some((repository, digest, media_types));
+                break;
+            },
+            Err(e) if is_manifest_unavailable(&e) => {
+                println!("Manifest not available for {}:{}: {}", repository, tag, e);
+                all_denied &= is_access_denied(&e);
+                attempts.push(format!("{}: {}", repository, e));
+            },
+            Err(e) => {
+                println!("Failed to get layer digest: {}", e);
+                return Err(anyhow::anyhow!("Failed to get layer digest: {}", e));
+            },
+        }
+    }
+    // Only a 404 shows the tag is missing; 401/403 everywhere may just mean the credentials lack access
+    let (repository, layer_digest, media_types) = found.ok_or_else(|| {
+        if all_denied && !attempts.is_empty() {
+            anyhow::anyhow!("Access to tag {} denied by registry ({})", tag, attempts.join("; "))
+        } else {
+            anyhow::anyhow!("Tag {} not available on registry ({})", tag, attempts.join("; "))
+        }
+    })?;
     
     // Check if we still have time for downloading
     if start_time.elapsed() >= max_duration {
@@ -303,46 +391,64 @@ async fn test_registry_with_manifest_approach(
     let remaining_duration = max_duration - start_time.elapsed();
     
     // Use tokio::task::spawn_blocking to run the synchronous ureq download in async context
-    let downloaded_bytes = tokio::task::spawn_blocking(move || {
-        download_with_ureq(&blob_url, remaining_duration)
+    let downloaded_bytes = tokio::task::spawn_blocking({
+        let registry_url = registry_url.to_string();
+        let repository = repository.to_string();
+        move || download_with_ureq(&blob_url, remaining_duration, &registry_url, &repository, credentials.as_ref())
     }).await??;
     
     println!("Downloaded {} bytes from {}", downloaded_bytes, registry_url);
-    Ok(downloaded_bytes)
+    Ok((downloaded_bytes, media_types))
 }
 
 // Simplified helper function to get the first layer digest - following the user's example
-fn get_first_layer_digest(registry_url: &str, repository: &str, tag: &str) -> Result<String, anyhow::Error> {
+fn get_first_layer_digest(
+    registry_url: &str,
+    repository: &str,
+    tag: &str,
+    credentials: Option<&RegistryCredentials>,
+    platform: &TargetPlatform,
+) -> Result<(String, ManifestMediaTypes), anyhow::Error> {
     println!("Fetching tag manifest for {}:{}", repository, tag);
     
-    // Step 1: Fetch tag manifest (exactly like user's example)
-    let manifest_list = fetch_tag_manifest(registry_url, repository, tag)
-        .map_err(|e| anyhow::anyhow!("Failed to fetch tag manifest: {}", e))?;
+    // Step 1: Fetch tag manifest (exactly like user's example); 401/403/404 are passed through so callers can try another path
+    let manifest_list = fetch_tag_manifest(registry_url, repository, tag, credentials).map_err(|e| {
+        if is_manifest_unavailable(&e) {
+            e
+        } else {
+            anyhow::anyhow!("Failed to fetch tag manifest: {}", e)
+        }
+    })?;
     
     if manifest_list.manifests.is_empty() {
         // Try direct manifest fetch as fallback
         println!("No manifests in list, trying direct manifest fetch");
-        let direct_manifest = fetch_digest_manifest(registry_url, repository, tag)
+        let direct_manifest = fetch_digest_manifest(registry_url, repository, tag, credentials)
             .map_err(|e| anyhow::anyhow!("Failed to fetch direct manifest: {}", e))?;
         
         if direct_manifest.layers.is_empty() {
             return Err(anyhow::anyhow!("No layers found in direct manifest"));
         }
         
-        return Ok(direct_manifest.layers[0].digest.clone());
+        let media_types = ManifestMediaTypes { index: None, manifest: direct_manifest.media_type };
+        return Ok((direct_manifest.layers[0].digest.clone(), media_types));
     }
     
-    // Step 2: Get first manifest digest (exactly like user's example)
-    let first_manifest_digest = &manifest_list.manifests[0].digest;
-    println!("First manifest digest: {}", first_manifest_digest);
+    // Step 2: Pick the manifest for the requested platform
+    let platform_manifest_digest = &select_platform_manifest(&manifest_list, platform)?.digest;
+    println!("Manifest digest for {}: {}", platform, platform_manifest_digest);
     
     // Step 3: Fetch digest manifest (exactly like user's example)
-    let digest_manifest = fetch_digest_manifest(registry_url, repository, first_manifest_digest)
+    let digest_manifest = fetch_digest_manifest(registry_url, repository, platform_manifest_digest, credentials)
         .map_err(|e| anyhow::anyhow!("Failed to fetch digest manifest: {}", e))?;
     
     if digest_manifest.layers.is_empty() {
         return Err(anyhow::anyhow!("No layers found in digest manifest"));
     }
+    let media_types = ManifestMediaTypes {
+        index: manifest_list.media_type.clone(),
+        manifest: digest_manifest.media_type.clone(),
+    };
     
     // Step 4: Get first layer digest (exactly like user's example)
     let layer_digest = &digest_manifest.layers[0].digest;
@@ -355,10 +461,10 @@ fn get_first_layer_digest(registry_url: &str, repository: &str, tag: &str) -> Re
             if layer.size > layer_size {
                 println!("Using larger layer {} instead: {}, size: {} bytes ({:.2} MB)", 
                     i, layer.digest, layer.size, layer.size as f64 / (1024.0 * 1024.0));
-                return Ok(layer.digest.clone());
+                return Ok((layer.digest.clone(), media_types));
             }
         }
     }
     
-    Ok(layer_digest.clone())
+    Ok((layer_digest.clone(), media_types))
 }
\ No newline at end of file
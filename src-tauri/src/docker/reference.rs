@@ -0,0 +1,322 @@
+use anyhow::Result;
+
+use super::credentials::registry_host;
+
+// Limits from the distribution reference grammar
+const MAX_NAME_LENGTH: usize = 255;
+const MAX_TAG_LENGTH: usize = 128;
+const DEFAULT_TAG: &str = "latest";
+
+// Parsed `[registry[:port]/]path[:tag][@digest]` image reference
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImageReference {
+    // Registry host as written, or docker.io when the reference names none
+    pub registry_host: String,
+    pub registry_port: Option<u16>,
+    // Repository path inside the registry, as written
+    pub path: String,
+    pub tag: Option<String>,
+    pub digest: Option<String>,
+}
+
+impl ImageReference {
+    pub fn parse(reference: &str) -> Result<Self> {
+        let reference = reference.trim();
+        if reference.is_empty() {
+            return Err(anyhow::anyhow!("Image name is empty"));
+        }
+        if reference.chars().any(char::is_whitespace) {
+            return Err(anyhow::anyhow!("Image name must not contain spaces"));
+        }
+
+        let (name_and_tag, digest) = match reference.split_once('@') {
+            Some((name_and_tag, digest)) => (name_and_tag, Some(parse_digest(digest)?)),
+            None => (reference, None),
+        };
+
+        // A colon after the last slash starts the tag; earlier ones belong to a registry port
+        let last_slash = name_and_tag.rfind('/').map_or(0, |i| i + 1);
+        let (name, tag) = match name_and_tag[last_slash..].find(':') {
+            Some(i) => {
+                let (name, tag) = name_and_tag.split_at(last_slash + i);
+                (name, Some(parse_tag(&tag[1..])?))
+            }
+            None => (name_and_tag, None),
+        };
+
+        if name.is_empty() {
+            return Err(anyhow::anyhow!("Image name is missing a repository"));
+        }
+        if name.len() > MAX_NAME_LENGTH {
+            return Err(anyhow::anyhow!("Image name is longer than {} characters", MAX_NAME_LENGTH));
+        }
+
+        // Like the Docker CLI, the first component is a registry only if it looks like a host
+        let (registry, path) = match name.split_once('/') {
+            Some((first, rest)) if first.contains(['.', ':']) || first == "localhost" || first.chars().any(|c| c.is_ascii_uppercase()) => {
+                (Some(first), rest)
+            }
+            _ => (None, name),
+        };
+
+        let (registry_host, registry_port) = match registry {
+            Some(registry) => parse_registry(registry)?,
+            None => ("docker.io".to_string(), None),
+        };
+        for component in path.split('/') {
+            validate_path_component(component, path)?;
+        }
+
+        Ok(Self {
+            registry_host,
+            registry_port,
+            path: path.to_string(),
+            tag,
+            digest,
+        })
+    }
+
+    pub fn is_docker_hub(&self) -> bool {
+        self.registry_port.is_none() && registry_host(&self.registry_host) == "docker.io"
+    }
+
+    // Path the registry API expects; Docker Hub keeps official images under library/
+    pub fn repository(&self) -> String {
+        if self.is_docker_hub() && !self.path.contains('/') {
+            format!("library/{}", self.path)
+        } else {
+            self.path.clone()
+        }
+    }
+
+    // Repository paths to try, canonical first; pull-through mirrors differ on whether official images need library/
+    pub fn repository_candidates(&self) -> Vec<String> {
+        let repository = self.repository();
+        if repository != self.path {
+            vec![repository, self.path.clone()]
+        } else {
+            vec![repository]
+        }
+    }
+
+    // Digest wins over the tag, as with `docker pull`
+    pub fn manifest_reference(&self) -> &str {
+        self.digest.as_deref().or(self.tag.as_deref()).unwrap_or(DEFAULT_TAG)
+    }
+}
+
+fn parse_registry(registry: &str) -> Result<(String, Option<u16>)> {
+    // IPv6 hosts are bracketed, e.g. [::1]:5000
+    let (host, port) = if let Some(rest) = registry.strip_prefix('[') {
+        let (host, after) = rest
+            .split_once(']')
+            .ok_or_else(|| anyhow::anyhow!("Registry {:?} has an unclosed IPv6 bracket", registry))?;
+        if host.parse::<std::net::Ipv6Addr>().is_err() {
+            return Err(anyhow::anyhow!("Registry {:?} is not a valid IPv6 address", registry));
+        }
+        match after {
+            "" => (format!("[{}]", host), None),
+            _ => match after.strip_prefix(':') {
+                Some(port) => (format!("[{}]", host), Some(port)),
+                None => return Err(anyhow::anyhow!("Unexpected characters after IPv6 address in registry {:?}", registry)),
+            },
+        }
+    } else {
+        let (host, port) = match registry.split_once(':') {
+            Some((host, port)) => (host, Some(port)),
+            None => (registry, None),
+        };
+        for label in host.split('.') {
+            let valid = !label.is_empty()
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+                && !label.starts_with('-')
+                && !label.ends_with('-');
+            if !valid {
+                return Err(anyhow::anyhow!("Invalid registry host {:?}", host));
+            }
+        }
+        (host.to_string(), port)
+    };
+
+    let port = match port {
+        Some(port) => Some(
+            port.parse::<u16>()
+                .ok()
+                .filter(|port| *port != 0)
+                .ok_or_else(|| anyhow::anyhow!("Invalid registry port {:?}", port))?,
+        ),
+        None => None,
+    };
+    Ok((host, port))
+}
+
+// Lowercase alphanumerics joined by `.`, `_`, `__` or runs of `-`
+fn validate_path_component(component: &str, path: &str) -> Result<()> {
+    if component.is_empty() {
+        return Err(anyhow::anyhow!("Repository {:?} has an empty path component", path));
+    }
+    if component.chars().any(|c| c.is_ascii_uppercase()) {
+        return Err(anyhow::anyhow!("Repository {:?} must be lowercase", path));
+    }
+    if let Some(c) = component.chars().find(|c| !matches!(c, 'a'..='z' | '0'..='9' | '.' | '_' | '-')) {
+        return Err(anyhow::anyhow!("Repository {:?} contains invalid character {:?}", path, c));
+    }
+
+    let bytes = component.as_bytes();
+    let is_separator = |b: u8| matches!(b, b'.' | b'_' | b'-');
+    if is_separator(bytes[0]) || is_separator(bytes[bytes.len() - 1]) {
+        return Err(anyhow::anyhow!("Repository component {:?} must start and end with a letter or digit", component));
+    }
+    let mut separators = component.split(|c: char| c.is_ascii_alphanumeric()).filter(|s| !s.is_empty());
+    if let Some(separator) = separators.find(|s| !matches!(*s, "." | "_" | "__") && !s.chars().all(|c| c == '-')) {
+        return Err(anyhow::anyhow!("Repository component {:?} has invalid separator {:?}", component, separator));
+    }
+    Ok(())
+}
+
+fn parse_tag(tag: &str) -> Result<String> {
+    if tag.is_empty() {
+        return Err(anyhow::anyhow!("Tag is empty"));
+    }
+    if tag.len() > MAX_TAG_LENGTH {
+        return Err(anyhow::anyhow!("Tag {:?} is longer than {} characters", tag, MAX_TAG_LENGTH));
+    }
+    if tag.starts_with(['.', '-']) {
+        return Err(anyhow::anyhow!("Tag {:?} must start with a letter, digit or underscore", tag));
+    }
+    if let Some(c) = tag.chars().find(|c| !(c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-'))) {
+        return Err(anyhow::anyhow!("Tag {:?} contains invalid character {:?}", tag, c));
+    }
+    Ok(tag.to_string())
+}
+
+// `algorithm:encoded`, e.g. sha256:<64 hex digits>
+fn parse_digest(digest: &str) -> Result<String> {
+    let (algorithm, encoded) = digest
+        .split_once(':')
+        .ok_or_else(|| anyhow::anyhow!("Digest {:?} must look like sha256:<hex>", digest))?;
+
+    let algorithm_valid = !algorithm.is_empty()
+        && algorithm.split(['+', '.', '_', '-']).all(|part| {
+            !part.is_empty() && part.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit())
+        });
+    if !algorithm_valid {
+        return Err(anyhow::anyhow!("Digest algorithm {:?} is invalid", algorithm));
+    }
+
+    match algorithm {
+        "sha256" | "sha512" => {
+            let expected = if algorithm == "sha256" { 64 } else { 128 };
+            if encoded.len() != expected || !encoded.chars().all(|c| matches!(c, '0'..='9' | 'a'..='f')) {
+                return Err(anyhow::anyhow!("{} digest must be {} lowercase hex characters", algorithm, expected));
+            }
+        }
+        _ => {
+            if encoded.is_empty() || !encoded.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '=' | '_' | '-')) {
+                return Err(anyhow::anyhow!("Digest {:?} has an invalid encoded part", digest));
+            }
+        }
+    }
+    Ok(digest.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DIGEST: &str = "sha256:0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef";
+
+    fn parse_error(reference: &str) -> String {
+        ImageReference::parse(reference).expect_err(reference).to_string()
+    }
+
+    #[test]
+    fn registry_with_port_and_tag() {
+        let reference = ImageReference::parse("localhost:5000/foo:tag").unwrap();
+
+        assert_eq!(reference.registry_host, "localhost");
+        assert_eq!(reference.registry_port, Some(5000));
+        assert_eq!(reference.path, "foo");
+        assert_eq!(reference.tag.as_deref(), Some("tag"));
+        assert_eq!(reference.digest, None);
+        assert!(!reference.is_docker_hub());
+        assert_eq!(reference.repository(), "foo");
+        assert_eq!(reference.manifest_reference(), "tag");
+    }
+
+    #[test]
+    fn ipv6_registry_with_digest() {
+        let reference = ImageReference::parse(&format!("[::1]:5000/foo@{}", DIGEST)).unwrap();
+
+        assert_eq!(reference.registry_host, "[::1]");
+        assert_eq!(reference.registry_port, Some(5000));
+        assert_eq!(reference.path, "foo");
+        assert_eq!(reference.tag, None);
+        assert_eq!(reference.manifest_reference(), DIGEST);
+    }
+
+    #[test]
+    fn official_docker_hub_image() {
+        let reference = ImageReference::parse("ubuntu").unwrap();
+
+        assert_eq!(reference.registry_host, "docker.io");
+        assert!(reference.is_docker_hub());
+        assert_eq!(reference.repository(), "library/ubuntu");
+        assert_eq!(reference.repository_candidates(), vec!["library/ubuntu", "ubuntu"]);
+        assert_eq!(reference.manifest_reference(), "latest");
+
+        let user_image = ImageReference::parse("someone/tool:1.0").unwrap();
+        assert_eq!(user_image.repository(), "someone/tool");
+        assert_eq!(user_image.repository_candidates(), vec!["someone/tool"]);
+    }
+
+    #[test]
+    fn digest_wins_over_tag() {
+        let reference = ImageReference::parse(&format!("ghcr.io/a/b:tag@{}", DIGEST)).unwrap();
+
+        assert_eq!(reference.registry_host, "ghcr.io");
+        assert_eq!(reference.path, "a/b");
+        assert_eq!(reference.tag.as_deref(), Some("tag"));
+        assert_eq!(reference.digest.as_deref(), Some(DIGEST));
+        assert_eq!(reference.manifest_reference(), DIGEST);
+    }
+
+    #[test]
+    fn uppercase_path_is_rejected() {
+        assert!(parse_error("Ubuntu").contains("must be lowercase"));
+        assert!(parse_error("ghcr.io/Owner/app").contains("must be lowercase"));
+    }
+
+    #[test]
+    fn invalid_separators_are_rejected() {
+        assert!(parse_error("a..b").contains("invalid separator \"..\""));
+        assert!(parse_error("a___b").contains("invalid separator \"___\""));
+        assert!(parse_error("-ab").contains("must start and end"));
+
+        for valid in ["a.b", "a_b", "a__b", "a---b"] {
+            assert!(ImageReference::parse(valid).is_ok(), "{} should parse", valid);
+        }
+    }
+
+    #[test]
+    fn tag_length_limit() {
+        let tag = "a".repeat(MAX_TAG_LENGTH);
+        assert!(ImageReference::parse(&format!("foo:{}", tag)).is_ok());
+        assert!(parse_error(&format!("foo:{}a", tag)).contains("longer than 128 characters"));
+    }
+
+    #[test]
+    fn malformed_digests_are_rejected() {
+        assert!(parse_error("foo@sha256:abc").contains("64 lowercase hex characters"));
+        assert!(parse_error(&format!("foo@sha256:{}", "A".repeat(64))).contains("64 lowercase hex characters"));
+        assert!(parse_error("foo@0123456789abcdef").contains("must look like sha256:<hex>"));
+        assert!(parse_error("foo@SHA256:abc").contains("algorithm"));
+    }
+
+    #[test]
+    fn out_of_range_ports_are_rejected() {
+        assert!(parse_error("localhost:0/foo").contains("Invalid registry port \"0\""));
+        assert!(parse_error("localhost:65536/foo").contains("Invalid registry port \"65536\""));
+        assert_eq!(ImageReference::parse("localhost:65535/foo").unwrap().registry_port, Some(65535));
+    }
+}
@@ -0,0 +1,273 @@
+use anyhow::Result;
+use futures_util::StreamExt;
+use reqwest::header::{CONTENT_RANGE, ETAG, IF_RANGE, LAST_MODIFIED, RANGE};
+use reqwest::{Client, StatusCode};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tokio::fs::{File, OpenOptions};
+use tokio::io::{AsyncReadExt, AsyncWriteExt, BufWriter};
+
+use crate::dns::{resolve_download_host, SYSTEM_DNS};
+use crate::usage::record_downloaded_bytes;
+
+// How often progress is reported while a file is downloading
+const FILE_PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+const RESOLVE_TIMEOUT_SECONDS: u64 = 10;
+// A stalled connection fails the download instead of keeping it "already downloading" forever
+const FILE_READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FileDownloadRequest {
+    pub url: String,
+    pub destination: String,
+    // Resolver to fetch through, usually the winner of a speed test; defaults to the system resolver
+    pub dns_server: Option<String>,
+    pub expected_sha256: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FileDownloadProgress {
+    pub url: String,
+    pub destination: String,
+    pub downloaded_bytes: u64,
+    pub total_bytes: Option<u64>,
+    pub speed_mbps: f64,
+    pub elapsed_seconds: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FileDownloadResult {
+    pub url: String,
+    pub destination: String,
+    pub dns_server: String,
+    pub resolved_ip: String,
+    pub downloaded_bytes: u64,
+    // Bytes already on disk from an earlier, paused attempt
+    pub resumed_from: u64,
+    pub sha256: String,
+    pub checksum_verified: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FileDownloadError {
+    pub url: String,
+    pub destination: String,
+    pub error_message: String,
+}
+
+// Partial data lives next to the destination until the download completes
+pub fn partial_path(destination: &str) -> PathBuf {
+    PathBuf::from(format!("{}.part", destination))
+}
+
+// ETag or Last-Modified of the response the partial file came from, sent back as If-Range on resume
+fn validator_path(destination: &str) -> PathBuf {
+    PathBuf::from(format!("{}.part.meta", destination))
+}
+
+// Weak ETags can't be used with If-Range, so fall back to Last-Modified for those
+fn response_validator(response: &reqwest::Response) -> Option<String> {
+    let header = |name| response.headers().get(name).and_then(|value| value.to_str().ok());
+    header(ETAG)
+        .filter(|etag| !etag.starts_with("W/"))
+        .or_else(|| header(LAST_MODIFIED))
+        .map(str::to_string)
+}
+
+// First byte and total size from a `Content-Range: bytes 100-199/12345` header
+fn content_range(response: &reqwest::Response) -> Option<(Option<u64>, Option<u64>)> {
+    let value = response.headers().get(CONTENT_RANGE)?.to_str().ok()?;
+    let (range, total) = value.strip_prefix("bytes ")?.split_once('/')?;
+    let start = range.split_once('-').and_then(|(start, _)| start.trim().parse().ok());
+    Some((start, total.trim().parse().ok()))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// Feed bytes already on disk into the hasher so a resumed download still gets a full checksum
+async fn hash_existing(path: &Path, hasher: &mut Sha256) -> Result<()> {
+    let mut file = File::open(path).await?;
+    let mut buffer = vec![0u8; 64 * 1024];
+    loop {
+        let n = file.read(&mut buffer).await?;
+        if n == 0 {
+            return Ok(());
+        }
+        hasher.update(&buffer[..n]);
+    }
+}
+
+// Download a URL to disk through the chosen DNS server, resuming from a previous partial file when possible
+pub async fn download_file<F>(request: &FileDownloadRequest, on_progress: F) -> Result<FileDownloadResult>
+where
+    F: Fn(FileDownloadProgress),
+{
+    let url = request.url.as_str();
+    let dns_server = request.dns_server.as_deref().unwrap_or(SYSTEM_DNS);
+
+    let parsed_url = reqwest::Url::parse(url)?;
+    let host = parsed_url.host_str().ok_or_else(|| anyhow::anyhow!("Invalid host"))?;
+    let port = parsed_url
+        .port_or_known_default()
+        .ok_or_else(|| anyhow::anyhow!("Unsupported scheme"))?;
+
+    let resolved = resolve_download_host(url, dns_server, RESOLVE_TIMEOUT_SECONDS, false).await?;
+    println!("Downloading {} via DNS {} ({})", url, dns_server, resolved.ip);
+
+    let client = Client::builder()
+        .connect_timeout(Duration::from_secs(30))
+        .read_timeout(FILE_READ_TIMEOUT)
+        .resolve(host, SocketAddr::new(resolved.ip, port))
+        .build()?;
+
+    let part_path = partial_path(&request.destination);
+    if let Some(parent) = part_path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    let meta_path = validator_path(&request.destination);
+    let existing_bytes = match tokio::fs::metadata(&part_path).await {
+        Ok(metadata) => metadata.len(),
+        Err(_) => 0,
+    };
+    // Without a validator there's no way to tell whether the remote file changed, so start over
+    let validator = match existing_bytes {
+        0 => None,
+        _ => tokio::fs::read_to_string(&meta_path).await.ok().filter(|v| !v.trim().is_empty()),
+    };
+
+    let mut http_request = client.get(url);
+    if let Some(validator) = &validator {
+        // If-Range makes the server send the whole file instead when it has changed since
+        http_request = http_request
+            .header(RANGE, format!("bytes={}-", existing_bytes))
+            .header(IF_RANGE, validator.trim());
+    }
+    let response = http_request.send().await
+        .map_err(|e| anyhow::anyhow!("HTTP request failed: {}", e))?;
+
+    let mut hasher = Sha256::new();
+    let (mut file, resumed_from, total_bytes) = match response.status() {
+        StatusCode::PARTIAL_CONTENT if validator.is_some() => {
+            let (start, total_bytes) = content_range(&response).unwrap_or((None, None));
+            if start != Some(existing_bytes) {
+                return Err(anyhow::anyhow!(
+                    "Server resumed at {:?} instead of byte {}; delete the partial file to start over",
+                    start,
+                    existing_bytes
+                ));
+            }
+            println!("Resuming {} from byte {}", url, existing_bytes);
+            hash_existing(&part_path, &mut hasher).await?;
+            let file = OpenOptions::new().append(true).open(&part_path).await?;
+            (file, existing_bytes, total_bytes)
+        }
+        // Nothing left to fetch: the partial file already holds the whole body
+        StatusCode::RANGE_NOT_SATISFIABLE if validator.is_some() => {
+            if let Some((_, Some(total_bytes))) = content_range(&response) {
+                if total_bytes != existing_bytes {
+                    return Err(anyhow::anyhow!(
+                        "Partial file has {} bytes but the remote file has {}; delete it to start over",
+                        existing_bytes,
+                        total_bytes
+                    ));
+                }
+            }
+            hash_existing(&part_path, &mut hasher).await?;
+            let file = OpenOptions::new().append(true).open(&part_path).await?;
+            (file, existing_bytes, Some(existing_bytes))
+        }
+        status if status.is_success() && status != StatusCode::PARTIAL_CONTENT => {
+            // Fresh download, or the file changed and the server sent all of it, so start over
+            let file = File::create(&part_path).await?;
+            match response_validator(&response) {
+                Some(validator) => tokio::fs::write(&meta_path, validator).await?,
+                None => {
+                    let _ = tokio::fs::remove_file(&meta_path).await;
+                }
+            }
+            (file, 0, response.content_length())
+        }
+        status => return Err(anyhow::anyhow!("HTTP error: {}", status)),
+    };
+
+    let mut writer = BufWriter::new(&mut file);
+    let mut downloaded_bytes = resumed_from;
+    let start_time = Instant::now();
+    let mut last_progress_time = Instant::now();
+
+    if response.status() != StatusCode::RANGE_NOT_SATISFIABLE {
+        let mut stream = response.bytes_stream();
+        while let Some(chunk_result) = stream.next().await {
+            let chunk = chunk_result.map_err(|e| anyhow::anyhow!("Stream error: {}", e))?;
+            writer.write_all(&chunk).await?;
+            hasher.update(&chunk);
+            downloaded_bytes += chunk.len() as u64;
+            record_downloaded_bytes(chunk.len() as u64);
+
+            if last_progress_time.elapsed() >= FILE_PROGRESS_INTERVAL {
+                let elapsed = start_time.elapsed().as_secs_f64();
+                on_progress(FileDownloadProgress {
+                    url: url.to_string(),
+                    destination: request.destination.clone(),
+                    downloaded_bytes,
+                    total_bytes,
+                    speed_mbps: ((downloaded_bytes - resumed_from) as f64 * 8.0) / (elapsed * 1_000_000.0),
+                    elapsed_seconds: elapsed,
+                });
+                last_progress_time = Instant::now();
+            }
+        }
+    }
+    writer.flush().await?;
+    drop(writer);
+    file.sync_all().await?;
+    drop(file);
+
+    if let Some(total_bytes) = total_bytes {
+        if downloaded_bytes < total_bytes {
+            return Err(anyhow::anyhow!(
+                "Connection closed after {} of {} bytes; start the download again to resume",
+                downloaded_bytes,
+                total_bytes
+            ));
+        }
+    }
+
+    let sha256 = to_hex(&hasher.finalize());
+    let checksum_verified = match &request.expected_sha256 {
+        Some(expected) => {
+            if !expected.trim().eq_ignore_ascii_case(&sha256) {
+                // A corrupt partial file would poison every later resume, so discard it
+                tokio::fs::remove_file(&part_path).await?;
+                let _ = tokio::fs::remove_file(&meta_path).await;
+                return Err(anyhow::anyhow!(
+                    "Checksum mismatch: expected {}, got {}",
+                    expected.trim(),
+                    sha256
+                ));
+            }
+            Some(true)
+        }
+        None => None,
+    };
+
+    tokio::fs::rename(&part_path, &request.destination).await?;
+    let _ = tokio::fs::remove_file(&meta_path).await;
+    println!("Downloaded {} bytes to {}", downloaded_bytes, request.destination);
+
+    Ok(FileDownloadResult {
+        url: url.to_string(),
+        destination: request.destination.clone(),
+        dns_server: dns_server.to_string(),
+        resolved_ip: resolved.ip.to_string(),
+        downloaded_bytes,
+        resumed_from,
+        sha256,
+        checksum_verified,
+    })
+}
@@ -2,6 +2,9 @@ mod dns;
 mod docker;
 mod commands;
 mod utils;
+mod presets;
+mod usage;
+mod downloader;
 
 pub use dns::{DnsTestResult, DownloadSpeedResult};
 pub use commands::*;
@@ -11,7 +14,7 @@ pub use utils::*;
 pub fn run() {
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
-        .invoke_handler(tauri::generate_handler![test_dns_servers, test_download_speed_all_dns, test_docker_registries, validate_docker_image, abort_all_tasks])
+        .invoke_handler(tauri::generate_handler![test_dns_servers, test_dns_stability, get_dns_servers, get_domain_presets, save_domain_presets, test_download_speed_all_dns, test_download_mirrors, test_upload_speed_all_dns, test_connect_latency_all_dns, test_librespeed_server, test_docker_registries, validate_docker_image, get_data_usage, set_data_budget, reset_data_usage, start_file_download, pause_file_download, abort_all_tasks])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
@@ -0,0 +1,109 @@
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use anyhow::Result;
+
+// Built-in bundles written to the presets file the first time it is read
+const DEFAULT_DOMAIN_PRESETS: &[(&str, &[&str])] = &[
+    ("Go toolchain", &["go.dev", "proxy.golang.org", "sum.golang.org", "pkg.go.dev", "storage.googleapis.com"]),
+    ("Python", &["pypi.org", "files.pythonhosted.org", "www.python.org"]),
+    ("Node", &["registry.npmjs.org", "nodejs.org", "registry.yarnpkg.com"]),
+    ("Android", &["developer.android.com", "dl.google.com", "maven.google.com", "services.gradle.org", "plugins.gradle.org"]),
+    ("Docker", &["registry-1.docker.io", "auth.docker.io", "production.cloudflare.docker.com", "hub.docker.com"]),
+    ("Rust", &["crates.io", "index.crates.io", "static.crates.io", "static.rust-lang.org"]),
+    ("AI APIs", &["api.openai.com", "api.anthropic.com", "generativelanguage.googleapis.com", "huggingface.co"]),
+];
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DomainPreset {
+    pub name: String,
+    pub domains: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DomainPresetsFile {
+    domain_presets: Vec<DomainPreset>,
+}
+
+// Stored next to dockerRegistry.yml so both configs can be edited in one place
+pub fn domain_presets_config_path() -> PathBuf {
+    let config_dir = dirs::config_dir().unwrap_or_else(|| PathBuf::from("."));
+    config_dir.join("bargozin").join("domainPresets.yml")
+}
+
+pub fn default_domain_presets() -> Vec<DomainPreset> {
+    DEFAULT_DOMAIN_PRESETS
+        .iter()
+        .map(|(name, domains)| DomainPreset {
+            name: name.to_string(),
+            domains: domains.iter().map(|d| d.to_string()).collect(),
+        })
+        .collect()
+}
+
+// Trimmed, non-empty and unique domains; tests are keyed by domain, so duplicates would overwrite each other
+fn normalize_preset(preset: &DomainPreset) -> DomainPreset {
+    let mut domains: Vec<String> = Vec::new();
+    for domain in preset.domains.iter().map(|d| d.trim()).filter(|d| !d.is_empty()) {
+        if !domains.iter().any(|existing| existing.eq_ignore_ascii_case(domain)) {
+            domains.push(domain.to_string());
+        }
+    }
+    DomainPreset {
+        name: preset.name.trim().to_string(),
+        domains,
+    }
+}
+
+pub async fn write_domain_presets_file(path: &Path, presets: &[DomainPreset]) -> Result<()> {
+    let content = serde_yaml::to_string(&DomainPresetsFile {
+        domain_presets: presets.iter().map(normalize_preset).collect(),
+    })?;
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    tokio::fs::write(path, content).await?;
+    Ok(())
+}
+
+pub async fn read_domain_presets_file(path: &Path) -> Result<Vec<DomainPreset>> {
+    let content = tokio::fs::read_to_string(path).await?;
+    let file: DomainPresetsFile = serde_yaml::from_str(&content)?;
+    Ok(file.domain_presets.iter().map(normalize_preset).collect())
+}
+
+// Read presets from disk, seeding the file with the built-in bundles if it doesn't exist yet
+pub async fn load_domain_presets() -> Result<Vec<DomainPreset>> {
+    let path = domain_presets_config_path();
+    if !path.exists() {
+        let presets = default_domain_presets();
+        write_domain_presets_file(&path, &presets).await?;
+        return Ok(presets);
+    }
+    read_domain_presets_file(&path).await
+}
+
+pub async fn find_domain_preset(name: &str) -> Result<DomainPreset> {
+    let name = name.trim();
+    load_domain_presets()
+        .await?
+        .into_iter()
+        .find(|preset| preset.name.eq_ignore_ascii_case(name))
+        .ok_or_else(|| anyhow::anyhow!("Domain preset '{}' not found", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalized_presets_have_unique_trimmed_domains() {
+        let preset = normalize_preset(&DomainPreset {
+            name: " Go ".to_string(),
+            domains: vec![" go.dev".into(), "".into(), "proxy.golang.org".into(), "GO.dev ".into(), "  ".into()],
+        });
+
+        assert_eq!(preset.name, "Go");
+        assert_eq!(preset.domains, vec!["go.dev", "proxy.golang.org"]);
+    }
+}
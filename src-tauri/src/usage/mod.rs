@@ -0,0 +1,80 @@
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+
+// Error reported by tests skipped or stopped because the budget is used up
+pub const DATA_BUDGET_EXCEEDED: &str = "Data budget exceeded";
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DataUsage {
+    pub session_bytes: u64,
+    pub budget_bytes: Option<u64>,
+    pub budget_exceeded: bool,
+}
+
+#[derive(Default)]
+struct DataUsageState {
+    session_bytes: u64,
+    budget_bytes: Option<u64>,
+    exceeded_reported: bool,
+}
+
+impl DataUsageState {
+    fn snapshot(&self) -> DataUsage {
+        DataUsage {
+            session_bytes: self.session_bytes,
+            budget_bytes: self.budget_bytes,
+            budget_exceeded: self.is_exceeded(),
+        }
+    }
+
+    fn is_exceeded(&self) -> bool {
+        self.budget_bytes
+            .is_some_and(|budget| self.session_bytes >= budget)
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref DATA_USAGE: Arc<Mutex<DataUsageState>> = Arc::new(Mutex::new(DataUsageState::default()));
+}
+
+// Usage accumulates across tests until the user starts a new session; the budget carries over
+pub fn reset_session_usage() {
+    let mut usage = DATA_USAGE.lock().unwrap();
+    usage.session_bytes = 0;
+    usage.exceeded_reported = false;
+}
+
+pub fn record_downloaded_bytes(bytes: u64) {
+    let mut usage = DATA_USAGE.lock().unwrap();
+    usage.session_bytes += bytes;
+}
+
+// Uploads use up the same budget as downloads
+pub fn record_uploaded_bytes(bytes: u64) {
+    record_downloaded_bytes(bytes);
+}
+
+pub fn current_data_usage() -> DataUsage {
+    DATA_USAGE.lock().unwrap().snapshot()
+}
+
+pub fn set_data_budget(budget_bytes: Option<u64>) -> DataUsage {
+    let mut usage = DATA_USAGE.lock().unwrap();
+    usage.budget_bytes = budget_bytes.filter(|&budget| budget > 0);
+    usage.exceeded_reported = false;
+    usage.snapshot()
+}
+
+pub fn data_budget_exceeded() -> bool {
+    DATA_USAGE.lock().unwrap().is_exceeded()
+}
+
+// Returns true only the first time the budget is seen exceeded in a session
+pub fn take_budget_exceeded_report() -> bool {
+    let mut usage = DATA_USAGE.lock().unwrap();
+    if usage.is_exceeded() && !usage.exceeded_reported {
+        usage.exceeded_reported = true;
+        return true;
+    }
+    false
+}
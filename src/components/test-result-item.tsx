@@ -3,6 +3,8 @@ import { useState } from "react";
 
 export default function TestResultItem(props: {
   dns: string;
+  // Shown next to the server when results for several domains are listed together
+  domain?: string;
   status: boolean;
   responseTime?: number;
   errorMessage?: string;
@@ -94,6 +96,11 @@ export default function TestResultItem(props: {
           <span className={`transition-colors translate-y-[2.5px] duration-200 text-md ${isCopied ? "text-green-400" : ""}`}>
             {props.dns}
           </span>
+          {props.domain && (
+            <span className="translate-y-[2.5px] text-xs text-gray-400">
+              {props.domain}
+            </span>
+          )}
         </p>
         {props.responseTime && (
           <p className="pl-1 w-calc(100%-50px) text-xs text-gray-400 text-left">
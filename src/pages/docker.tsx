@@ -93,27 +93,20 @@ export default function Docker() {
     // Basic frontend validation for better UX
     const trimmedDomain = domain.trim();
 
-    // Validate Docker image name format
+    // Validate Docker image name format; an invalid name rejects with the reason
     try {
-      const isValid = await invoke<boolean>("validate_docker_image", {
+      await invoke<void>("validate_docker_image", {
         imageName: trimmedDomain,
       });
-
-      if (!isValid) {
-        toast.error(
-          "لطفاً یک نام ایمیج داکر معتبر وارد کنید (مثلا: ubuntu:latest)",
-          {
-            position: "top-left",
-            className: "dir-fa text-right",
-          }
-        );
-        return;
-      }
     } catch (error) {
-      toast.error("خطا در اعتبارسنجی نام ایمیج: " + error, {
-        position: "top-left",
-        className: "dir-fa text-right",
-      });
+      toast.error(
+        "لطفاً یک نام ایمیج داکر معتبر وارد کنید (مثلا: ubuntu:latest): " +
+          error,
+        {
+          position: "top-left",
+          className: "dir-fa text-right",
+        }
+      );
       return;
     }
 
@@ -17,6 +17,12 @@ interface DnsTestResult {
   response_time?: number;
   error_message?: string;
   session_id: number;
+  test_url?: string;
+}
+
+interface DomainPreset {
+  name: string;
+  domains: string[];
 }
 
 export default function DomainTest() {
@@ -31,6 +37,9 @@ export default function DomainTest() {
   const [unusableResults, setUnusableResults] = useState<DnsTestResult[]>([]);
   const [isLoading, setIsLoading] = useState(false);
   const [isCompleted, setIsCompleted] = useState(false);
+  const [presets, setPresets] = useState<DomainPreset[]>([]);
+  const [activePreset, setActivePreset] = useState<DomainPreset | null>(null);
+  const [dnsServerCount, setDnsServerCount] = useState(0);
 
   const scrollToBottom = (ref: React.RefObject<HTMLDivElement>) => {
     if (ref.current) {
@@ -82,6 +91,14 @@ export default function DomainTest() {
 
     initializeSession();
     invoke("abort_all_tasks");
+
+    invoke<string[]>("get_dns_servers")
+      .then((servers) => setDnsServerCount(servers.length))
+      .catch((error) => console.error("Failed to load DNS servers:", error));
+
+    invoke<DomainPreset[]>("get_domain_presets")
+      .then(setPresets)
+      .catch((error) => console.error("Failed to load domain presets:", error));
   }, []);
 
   const handleDnsTest = async () => {
@@ -117,6 +134,7 @@ export default function DomainTest() {
     setIsCompleted(false);
     setUsableResults([]);
     setUnusableResults([]);
+    setActivePreset(null);
 
     try {
       await invoke("test_dns_servers", {
@@ -129,8 +147,39 @@ export default function DomainTest() {
     }
   };
 
+  // Test every domain of a saved preset against all DNS servers in one go
+  const handlePresetTest = async (preset: DomainPreset) => {
+    if (isLoading) {
+      return;
+    }
+
+    setIsLoading(true);
+    setIsCompleted(false);
+    setUsableResults([]);
+    setUnusableResults([]);
+    setActivePreset(preset);
+
+    try {
+      await invoke("test_dns_servers", {
+        domain: "",
+        preset: preset.name,
+      });
+    } catch (error) {
+      console.error("DNS preset test error:", error);
+      showError("خطا در انجام تست DNS: " + error);
+      setIsLoading(false);
+    }
+  };
+
+  const resultDomain = (result: DnsTestResult) =>
+    activePreset && result.test_url
+      ? result.test_url.replace("https://", "").replace(/\/$/, "")
+      : undefined;
+
   const totalResults = usableResults.length + unusableResults.length;
-  const totalExpected = 26; // Total number of DNS servers
+  // Presets come back from the backend trimmed and deduplicated
+  const totalExpected =
+    dnsServerCount * (activePreset ? activePreset.domains.length : 1);
 
   return (
     <div className="text-right h-full flex flex-col pr-[35px]">
@@ -170,7 +219,7 @@ export default function DomainTest() {
                   : ""
                   }`}
                 style={{
-                  width: `${(totalResults / totalExpected) * 100}%`,
+                  width: `${totalExpected > 0 ? (totalResults / totalExpected) * 100 : 0}%`,
                 }}
               ></div>
             </div>
@@ -209,6 +258,25 @@ export default function DomainTest() {
               : "بررسی DNS ها"}
           </button>
         </div>
+
+        {presets.length > 0 && (
+          <div className="mb-4 flex flex-wrap justify-end gap-2 dir-en">
+            {presets.map((preset) => (
+              <button
+                key={preset.name}
+                onClick={() => handlePresetTest(preset)}
+                disabled={isLoading}
+                title={preset.domains.join("\n")}
+                className={`px-3 py-1 rounded-lg text-sm transition-colors duration-200 ${activePreset?.name === preset.name
+                  ? "bg-green-500/25 text-white"
+                  : "bg-white/5 text-gray-300 hover:text-white hover:bg-white/10"
+                  } disabled:opacity-50 disabled:cursor-not-allowed cursor-pointer`}
+              >
+                {preset.name}
+              </button>
+            ))}
+          </div>
+        )}
       </div>
 
       {/* Results Section - Takes remaining space */}
@@ -232,6 +300,7 @@ export default function DomainTest() {
                   <TestResultItem
                     key={`usable-${index}`}
                     dns={result.dns_server}
+                    domain={resultDomain(result)}
                     status={result.status}
                     responseTime={result.response_time}
                     errorMessage={result.error_message}
@@ -291,6 +360,7 @@ export default function DomainTest() {
                   <TestResultItem
                     key={`unusable-${index}`}
                     dns={result.dns_server}
+                    domain={resultDomain(result)}
                     status={result.status}
                     responseTime={Number(result.response_time?.toFixed(0))}
                     errorMessage={result.error_message}